@@ -0,0 +1,341 @@
+//		Imports
+use std::collections::HashMap;
+use rand::{Rng, seq::SliceRandom};
+use serde::{Deserialize, Serialize};
+
+use twilight_model::{
+	channel::message::AllowedMentions,
+	gateway::payload::incoming::MessageCreate,
+	id::{Id, marker::UserMarker}
+};
+
+use crate::{
+	BotResult,
+	InteractionContext,
+	commands::dice::{MAX_MESSAGE, rng, send_dm, take_option, truncate},
+	data::ChannelData
+};
+
+//		Data
+//	A channel's deck: what's left to draw, what's been played, and what each player
+//	is holding.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deck {
+	//	Top of the pile last
+	pub draw:Vec<String>,
+	pub discard:Vec<String>,
+	pub hands:HashMap<Id<UserMarker>, Vec<String>>
+}
+
+const SUITS: [&str; 4] = ["Clubs", "Diamonds", "Hearts", "Spades"];
+const RANKS: [&str; 13] = ["Ace", "2", "3", "4", "5", "6", "7", "8", "9", "10", "Jack", "Queen", "King"];
+
+const ARCANA: [&str; 22] = [
+	"The Fool", "The Magician", "The High Priestess", "The Empress", "The Emperor", "The Hierophant",
+	"The Lovers", "The Chariot", "Strength", "The Hermit", "Wheel of Fortune", "Justice",
+	"The Hanged Man", "Death", "Temperance", "The Devil", "The Tower", "The Star", "The Moon",
+	"The Sun", "Judgement", "The World"
+];
+const TAROT_SUITS: [&str; 4] = ["Wands", "Cups", "Swords", "Pentacles"];
+const TAROT_RANKS: [&str; 14] = [
+	"Ace", "2", "3", "4", "5", "6", "7", "8", "9", "10", "Page", "Knight", "Queen", "King"
+];
+
+//	Cards one message can draw, deal or peek at
+const MAX_DRAW: usize = 20;
+const MAX_CUSTOM_CARDS: usize = 200;
+
+//		Command
+//	"new", "shuffle" and "reshuffle" take "--seed N" the way dice do.
+pub async fn deck(
+	ctx: InteractionContext,
+	msg: Box<MessageCreate>,
+	rest: &str
+) -> BotResult<()> {
+	let _lock = ChannelData::lock(msg.channel_id).await;
+	let mut channel: ChannelData = ChannelData::read_or_new(msg.channel_id).await?;
+
+	let (rest, seed) = match take_option(rest, "--seed") {
+		Ok(found) => found,
+		Err(e) => {
+			ctx.http.create_message(msg.channel_id).content(&e)?.await?;
+			return Ok(())
+		}
+	};
+	let (sub, args) = rest.split_once(' ').unwrap_or((&rest, ""));
+	let args = args.trim();
+	//	Shows which seed shuffled, when one was asked for
+	let seeded = |reply: String| match seed {
+		Some(seed) => format!("{} (seed {})", reply, seed),
+		None => reply
+	};
+
+	//	Making a deck is the only thing to do without one. The rng can't be held across
+	//	an await, so each shuffle makes its own.
+	if sub == "new" {
+		let made = Deck::new(args, &mut rng(seed));
+		let reply = match made {
+			Ok(deck) => {
+				let reply = seeded(format!("shuffled a new deck of {} cards", deck.draw.len()));
+				channel.deck = Some(deck);
+				channel.write_file().await?;
+				reply
+			}
+			Err(e) => e
+		};
+		ctx.http.create_message(msg.channel_id).content(&reply)?.await?;
+		return Ok(())
+	}
+	let Some(deck) = channel.deck.as_mut() else {
+		ctx.http.create_message(msg.channel_id)
+			.content("there's no deck here yet; start one with `!deck new [standard|jokers|tarot|<card>, <card>, ...]`")?
+			.await?;
+		return Ok(())
+	};
+
+	//	Private replies go by DM, public ones to the channel
+	let (reply, private): (String, Option<String>) = match sub {
+		"draw" => match count(args) {
+			Ok(n) => match deck.draw(n) {
+				Some(cards) => {
+					let reply = format!("<@{}> drew {}", msg.author.id, cards.join(", "));
+					deck.discard.extend(cards);
+					(reply, None)
+				}
+				None => (empty(deck), None)
+			},
+			Err(e) => (e, None)
+		},
+		"deal" => match count(args) {
+			Ok(n) => match deck.draw(n) {
+				Some(cards) => {
+					let hand = deck.hands.entry(msg.author.id).or_default();
+					hand.extend(cards.iter().cloned());
+					(
+						format!("<@{}> took {} card{} into their hand", msg.author.id, n, if n == 1 { "" } else { "s" }),
+						Some(format!("you drew {}\n{}", cards.join(", "), show_hand(hand)))
+					)
+				}
+				None => (empty(deck), None)
+			},
+			Err(e) => (e, None)
+		},
+		"hand" => {
+			let hand = deck.hands.get(&msg.author.id).map(Vec::as_slice).unwrap_or_default();
+			("your hand is in your DMs".to_string(), Some(show_hand(hand)))
+		}
+		"discard" => match deck.discard_from_hand(msg.author.id, args) {
+			Ok(cards) => (format!("<@{}> discarded {}", msg.author.id, cards.join(", ")), None),
+			Err(e) => (e, None)
+		},
+		"peek" => match count(args) {
+			Ok(n) => {
+				let top: Vec<&str> = deck.draw.iter().rev().take(n).map(String::as_str).collect();
+				match top.is_empty() {
+					true => ("the draw pile is empty".to_string(), None),
+					false => (
+						format!("<@{}> peeked at the top {} card{}", msg.author.id, top.len(), if top.len() == 1 { "" } else { "s" }),
+						Some(format!("the top of the deck, in order: {}", top.join(", ")))
+					)
+				}
+			}
+			Err(e) => (e, None)
+		},
+		"shuffle" => {
+			deck.draw.shuffle(&mut rng(seed));
+			(seeded(format!("shuffled the {} cards left in the draw pile", deck.draw.len())), None)
+		}
+		"reshuffle" => {
+			let count = deck.discard.len();
+			deck.reshuffle(&mut rng(seed));
+			(seeded(format!("shuffled {} discards back in; {} cards to draw", count, deck.draw.len())), None)
+		}
+		"status" => (deck.status(), None),
+		_ => ("usage: deck new [kind] | draw [n] | deal [n] | hand | discard <card|number|all> | peek [n] | shuffle | reshuffle | status, with --seed N to shuffle the same way again".to_string(), None)
+	};
+
+	if let Some(private) = private {
+		send_dm(&ctx, msg.author.id, &truncate(&private, MAX_MESSAGE)).await?;
+	}
+	channel.write_file().await?;
+
+	ctx.http.create_message(msg.channel_id)
+		.allowed_mentions(Some(&AllowedMentions::default()))
+		.content(&truncate(&reply, MAX_MESSAGE))?
+		.await?;
+
+	Ok(())
+}
+
+//		Functions
+fn count(args: &str) -> Result<usize, String> {
+	match args {
+		"" => Ok(1),
+		n => n.parse::<usize>().ok()
+			.filter(|n| (1..=MAX_DRAW).contains(n))
+			.ok_or(format!("you can take 1 to {} cards at a time", MAX_DRAW))
+	}
+}
+
+fn empty(deck: &Deck) -> String {
+	format!(
+		"not enough cards left to draw ({}); `!deck reshuffle` shuffles the {} discards back in",
+		deck.draw.len(), deck.discard.len()
+	)
+}
+
+fn show_hand(hand: &[String]) -> String {
+	if hand.is_empty() { return "your hand is empty".to_string() }
+
+	let cards: Vec<String> = hand.iter().enumerate()
+		.map(|(i, card)| format!("{}. {}", i + 1, card))
+		.collect();
+	format!("your hand:\n{}", cards.join("\n"))
+}
+
+impl Deck {
+	//	"standard" (the default) is 52 cards, "jokers" adds a red & black joker as
+	//	Savage Worlds uses, and "tarot" is the full 78. Anything else is a list of cards
+	//	separated by commas.
+	pub fn new(kind: &str, rng: &mut (impl Rng + ?Sized)) -> Result<Self, String> {
+		let standard = || SUITS.iter()
+			.flat_map(|suit| RANKS.iter().map(move |rank| format!("{} of {}", rank, suit)))
+			.collect::<Vec<String>>();
+
+		let mut draw: Vec<String> = match kind.trim().to_lowercase().as_str() {
+			"" | "standard" => standard(),
+			"jokers" => {
+				let mut cards = standard();
+				cards.extend(["Red Joker".to_string(), "Black Joker".to_string()]);
+				cards
+			}
+			"tarot" => ARCANA.iter().map(|card| card.to_string())
+				.chain(TAROT_SUITS.iter().flat_map(|suit| TAROT_RANKS.iter().map(move |rank| format!("{} of {}", rank, suit))))
+				.collect(),
+			_ => {
+				let cards: Vec<String> = kind.split(',')
+					.map(|card| card.trim().to_owned())
+					.filter(|card| !card.is_empty())
+					.collect();
+				if cards.len() < 2 || cards.len() > MAX_CUSTOM_CARDS {
+					return Err(format!("a custom deck needs 2 to {} cards, separated by commas", MAX_CUSTOM_CARDS))
+				}
+				cards
+			}
+		};
+		draw.shuffle(rng);
+
+		Ok(Deck{ draw, ..Default::default() })
+	}
+
+	//	Takes `n` cards off the top, or none if there aren't that many.
+	fn draw(&mut self, n: usize) -> Option<Vec<String>> {
+		if n > self.draw.len() { return None }
+
+		Some((0..n).filter_map(|_| self.draw.pop()).collect())
+	}
+
+	//	Puts the discard pile back into the draw pile and shuffles it. Cards in hands
+	//	stay where they are.
+	fn reshuffle(&mut self, rng: &mut (impl Rng + ?Sized)) {
+		self.draw.append(&mut self.discard);
+		self.draw.shuffle(rng);
+	}
+
+	//	Moves cards from a hand to the discard pile, by name, by number in the hand, or
+	//	"all" of them.
+	fn discard_from_hand(&mut self, user: Id<UserMarker>, which: &str) -> Result<Vec<String>, String> {
+		let Some(hand) = self.hands.get_mut(&user) else {
+			return Err("you don't have any cards in your hand".to_string())
+		};
+
+		let cards: Vec<String> = match which.trim() {
+			"" => return Err("usage: deck discard <card|number|all>".to_string()),
+			"all" => std::mem::take(hand),
+			which => {
+				let index = match which.parse::<usize>() {
+					Ok(n) if (1..=hand.len()).contains(&n) => Some(n - 1),
+					_ => hand.iter().position(|card| card.eq_ignore_ascii_case(which))
+				};
+				match index {
+					Some(index) => vec![hand.remove(index)],
+					None => return Err(format!("you aren't holding {}", which))
+				}
+			}
+		};
+		if hand.is_empty() { self.hands.remove(&user); }
+
+		self.discard.extend(cards.iter().cloned());
+		Ok(cards)
+	}
+
+	fn status(&self) -> String {
+		let held: usize = self.hands.values().map(Vec::len).sum();
+		let mut reply = format!("{} to draw · {} discarded · {} in hands", self.draw.len(), self.discard.len(), held);
+		if let Some(card) = self.discard.last() {
+			reply += &format!("\nlast played: {}", card);
+		}
+
+		reply
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::HashSet;
+	use rand::{SeedableRng, rngs::StdRng};
+
+	#[test]
+	fn kinds() {
+		let mut rng = StdRng::seed_from_u64(0);
+		let sizes: Vec<usize> = ["", "standard", "jokers", "tarot", "fire, ice, lightning"].iter()
+			.map(|kind| Deck::new(kind, &mut rng).unwrap().draw.len())
+			.collect();
+		assert_eq!(sizes, [52, 52, 54, 78, 3]);
+
+		let tarot = Deck::new("tarot", &mut rng).unwrap();
+		assert_eq!(tarot.draw.iter().collect::<HashSet<_>>().len(), 78);
+		assert!(tarot.draw.contains(&"The Tower".to_string()));
+
+		assert!(Deck::new("just one", &mut rng).is_err());
+
+		//	The same seed, the same order
+		assert_eq!(Deck::new("tarot", &mut super::rng(Some(3))), Deck::new("tarot", &mut super::rng(Some(3))));
+	}
+
+	#[test]
+	fn drawing() {
+		let mut rng = StdRng::seed_from_u64(0);
+		let mut deck = Deck::new("jokers", &mut rng).unwrap();
+
+		let top = deck.draw.last().cloned().unwrap();
+		let cards = deck.draw(5).unwrap();
+		assert_eq!(cards[0], top);
+		assert_eq!(deck.draw.len(), 49);
+		deck.discard.extend(cards);
+
+		assert_eq!(deck.draw(50), None);
+		assert_eq!(deck.draw.len(), 49);
+
+		deck.reshuffle(&mut rng);
+		assert_eq!((deck.draw.len(), deck.discard.len()), (54, 0));
+	}
+
+	#[test]
+	fn hands() {
+		let mut deck = Deck{ draw: vec![], ..Default::default() };
+		let user = Id::new(1);
+		deck.hands.insert(user, ["Ace of Spades", "2 of Hearts", "King of Clubs"].map(str::to_owned).to_vec());
+
+		assert_eq!(deck.discard_from_hand(user, "ace of spades"), Ok(vec!["Ace of Spades".to_owned()]));
+		assert_eq!(deck.discard_from_hand(user, "2"), Ok(vec!["King of Clubs".to_owned()]));
+		assert!(deck.discard_from_hand(user, "Red Joker").is_err());
+		assert_eq!(deck.discard_from_hand(user, "all"), Ok(vec!["2 of Hearts".to_owned()]));
+		assert!(deck.discard_from_hand(user, "all").is_err());
+
+		assert!(deck.hands.is_empty());
+		assert_eq!(deck.discard.len(), 3);
+		assert_eq!(deck.status(), "0 to draw · 3 discarded · 0 in hands\nlast played: 2 of Hearts");
+	}
+}
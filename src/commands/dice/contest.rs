@@ -0,0 +1,275 @@
+//		Imports
+use std::{cmp::Ordering, fmt};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use twilight_model::{
+	gateway::payload::incoming::MessageCreate,
+	id::{Id, marker::UserMarker}
+};
+
+use crate::{
+	BotResult,
+	InteractionContext,
+	data::{GuildData, UserData}
+};
+
+use super::{
+	DiceCommand, GameMaster, MAX_MESSAGE, RollLimits,
+	is_manager, load, scope, truncate,
+	resolve::Scope,
+	roll::RollResult
+};
+
+//		Data
+//	What happens when both sides of a contest roll the same.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TieRule {
+	//	Nobody wins, and things stay as they were
+	#[default]
+	Draw,
+	//	Whoever started the contest wins
+	Challenger,
+	//	Whoever was challenged wins
+	Defender,
+	//	Both sides roll again
+	Reroll,
+}
+
+//	Ties rerolled more often than this are left as a draw
+const MAX_REROLLS: u32 = 10;
+
+const TIE_RULES: [TieRule; 4] = [TieRule::Draw, TieRule::Challenger, TieRule::Defender, TieRule::Reroll];
+
+impl TieRule {
+	fn name(self) -> &'static str {
+		match self {
+			TieRule::Draw => "draw",
+			TieRule::Challenger => "challenger",
+			TieRule::Defender => "defender",
+			TieRule::Reroll => "reroll",
+		}
+	}
+}
+
+impl fmt::Display for TieRule {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+
+//	One side of a contest: who's rolling, and what with.
+struct Side {
+	name:String,
+	command:DiceCommand
+}
+
+//		Functions
+//	"vs <@user> <expr> [<expr>]" rolls the challenger's expression against the other
+//	user's, which is the same one if it's left out. Expressions with spaces in them are
+//	split with a '|'. "vs tie [rule]" shows or changes what a tie means.
+pub(super) async fn vs_reply(
+	ctx: &InteractionContext,
+	msg: &MessageCreate,
+	guild_data: Option<&mut GuildData>,
+	user_data: &UserData,
+	args: &str,
+	limits: &RollLimits
+) -> BotResult<String> {
+	let (first, rest) = args.trim().split_once(' ').unwrap_or((args.trim(), ""));
+	if first == "tie" {
+		return tie_reply(ctx, msg, guild_data, rest).await
+	}
+
+	let usage = "usage: vs <@user> <expr> [<their expr>], with a | between expressions that have spaces";
+	let Some(GameMaster::User(opponent)) = GameMaster::from_mention(first) else {
+		return Ok(usage.to_string())
+	};
+	let exprs: Vec<&str> = match rest.contains('|') {
+		true => rest.split('|').map(str::trim).collect(),
+		false => rest.split_whitespace().collect()
+	};
+	let (challenger_expr, opponent_expr) = match exprs[..] {
+		[expr] => (expr, expr),
+		[challenger, opponent] => (challenger, opponent),
+		_ => return Ok(usage.to_string())
+	};
+
+	//	Each side rolls with their own macros & variables
+	let (_, opponent_data) = load(None, opponent).await?;
+	let guild_data = guild_data.as_deref();
+	let rule = guild_data.map(|data| data.tie_rule).unwrap_or_default();
+
+	let challenger = match side(msg.author.id, challenger_expr, limits, &scope(user_data, guild_data)) {
+		Ok(side) => side,
+		Err(e) => return Ok(e)
+	};
+	let opponent = match side(opponent, opponent_expr, limits, &scope(&opponent_data, guild_data)) {
+		Ok(side) => side,
+		Err(e) => return Ok(format!("{}'s roll: {}", opponent, e))
+	};
+
+	Ok(contest(&challenger, &opponent, rule, limits.max_output, &mut rand::thread_rng()).unwrap_or_else(|e| e))
+}
+
+fn side(user: Id<UserMarker>, expr: &str, limits: &RollLimits, scope: &Scope) -> Result<Side, String> {
+	let command = DiceCommand::parse_with(expr, limits)
+		.map_err(|e| e.to_string())?
+		.resolve(scope, limits)
+		.map_err(|e| e.to_string())?;
+
+	Ok(Side{ name: format!("<@{}>", user), command })
+}
+
+impl Side {
+	fn roll(&self, rng: &mut (impl Rng + ?Sized)) -> Result<RollResult, String> {
+		self.command.roll(rng).map_err(|e| e.to_string())
+	}
+}
+
+//	Rolls both sides, rolling again on a tie if the rule says to, and says who won.
+//	Rolls longer than `max_output` only show their totals, as does everything if the
+//	whole contest won't fit in a message.
+fn contest(
+	challenger: &Side,
+	opponent: &Side,
+	rule: TieRule,
+	max_output: usize,
+	rng: &mut (impl Rng + ?Sized)
+) -> Result<String, String> {
+	let mut rounds: Vec<(RollResult, RollResult)> = vec![];
+	let mut outcome = String::new();
+
+	for attempt in 0..=MAX_REROLLS {
+		let (ours, theirs) = (challenger.roll(rng)?, opponent.roll(rng)?);
+		let (a, b) = (ours.total, theirs.total);
+		rounds.push((ours, theirs));
+
+		outcome = match (a.cmp(&b), rule) {
+			(Ordering::Greater, _) => format!("{} wins by {}", challenger.name, a.abs_diff(b)),
+			(Ordering::Less, _) => format!("{} wins by {}", opponent.name, a.abs_diff(b)),
+			(Ordering::Equal, TieRule::Reroll) if attempt < MAX_REROLLS => continue,
+			(Ordering::Equal, TieRule::Challenger) => format!("tied at {}; the challenger, {}, wins", a, challenger.name),
+			(Ordering::Equal, TieRule::Defender) => format!("tied at {}; the defender, {}, wins", a, opponent.name),
+			(Ordering::Equal, _) => format!("tied at {}; nobody wins", a)
+		};
+		break
+	}
+
+	let show = |side: &Side, roll: &RollResult, full: bool| match roll.to_string() {
+		_ if !full => format!("{}: {}", side.name, roll.total),
+		text if text.chars().count() > max_output => format!("{}: {} (too many dice to show)", side.name, roll.total),
+		text => format!("{}: {}", side.name, text)
+	};
+	let render = |full: bool| {
+		let mut lines = vec![format!("**{}** vs **{}**", challenger.name, opponent.name)];
+		for (i, (ours, theirs)) in rounds.iter().enumerate() {
+			lines.push(show(challenger, ours, full));
+			lines.push(show(opponent, theirs, full));
+			if i + 1 < rounds.len() { lines.push(format!("tied at {}, rolling again", ours.total)); }
+		}
+		lines.push(format!("**{}**", outcome));
+		lines.join("\n")
+	};
+
+	Ok(match render(true) {
+		reply if reply.chars().count() > MAX_MESSAGE => truncate(&render(false), MAX_MESSAGE),
+		reply => reply
+	})
+}
+
+//	"vs tie" shows the guild's tie rule, "vs tie <rule>" changes it.
+async fn tie_reply(
+	ctx: &InteractionContext,
+	msg: &MessageCreate,
+	guild_data: Option<&mut GuildData>,
+	args: &str
+) -> BotResult<String> {
+	let names: Vec<&str> = TIE_RULES.iter().map(|rule| rule.name()).collect();
+	let rule = match args.trim() {
+		"" => {
+			let rule = guild_data.map(|data| data.tie_rule).unwrap_or_default();
+			return Ok(format!("ties in contests here go: {}", rule))
+		}
+		name => match TIE_RULES.into_iter().find(|rule| rule.name() == name) {
+			Some(rule) => rule,
+			None => return Ok(format!("unknown tie rule `{}`; try {}", name, names.join(", ")))
+		}
+	};
+
+	let Some(data) = guild_data else {
+		return Ok("the tie rule can only be changed in a server".to_string())
+	};
+	if !is_manager(ctx, msg, data).await? {
+		return Ok("only server managers can change the tie rule".to_string())
+	}
+	data.tie_rule = rule;
+	data.write_file().await?;
+
+	Ok(format!("ties in contests here now go: {}", rule))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rand::{SeedableRng, rngs::StdRng};
+
+	const MAX_OUTPUT: usize = 1900;
+
+	fn test_side(user: u64, expr: &str) -> Side {
+		side(Id::new(user), expr, &RollLimits::default(), &Scope::default()).unwrap()
+	}
+
+	#[test]
+	fn winners() {
+		let strong = test_side(1, "10");
+		let weak = test_side(2, "1d4");
+
+		for seed in 0..20 {
+			let reply = contest(&strong, &weak, TieRule::Draw, MAX_OUTPUT, &mut StdRng::seed_from_u64(seed)).unwrap();
+			assert!(reply.starts_with("**<@1>** vs **<@2>**\n<@1>: 10 = 10\n<@2>: 1d4 ["), "{}", reply);
+			assert!(reply.contains("\n**<@1> wins by "), "{}", reply);
+		}
+	}
+
+	#[test]
+	fn ties() {
+		let a = test_side(1, "5");
+		let b = test_side(2, "2 + 3");
+		let mut rng = StdRng::seed_from_u64(0);
+
+		assert!(contest(&a, &b, TieRule::Draw, MAX_OUTPUT, &mut rng).unwrap().ends_with("**tied at 5; nobody wins**"));
+		assert!(contest(&a, &b, TieRule::Challenger, MAX_OUTPUT, &mut rng).unwrap().ends_with("the challenger, <@1>, wins**"));
+		assert!(contest(&a, &b, TieRule::Defender, MAX_OUTPUT, &mut rng).unwrap().ends_with("the defender, <@2>, wins**"));
+
+		//	Numbers tie forever, so the rerolls give up
+		let reply = contest(&a, &b, TieRule::Reroll, MAX_OUTPUT, &mut rng).unwrap();
+		assert_eq!(reply.matches("rolling again").count(), MAX_REROLLS as usize);
+		assert!(reply.ends_with("**tied at 5; nobody wins**"));
+
+		//	Dice settle it eventually
+		let c = test_side(1, "1d20");
+		let d = test_side(2, "1d20");
+		for seed in 0..50 {
+			let reply = contest(&c, &d, TieRule::Reroll, MAX_OUTPUT, &mut StdRng::seed_from_u64(seed)).unwrap();
+			assert!(reply.contains(" wins by ") && !reply.contains("nobody"), "{}", reply);
+		}
+	}
+
+	#[test]
+	fn fits_a_message() {
+		//	Big pools that tie every time
+		let a = test_side(1, "400d1");
+		let b = test_side(2, "200d1 + 200d1");
+		let mut rng = StdRng::seed_from_u64(0);
+
+		let reply = contest(&a, &b, TieRule::Reroll, MAX_OUTPUT, &mut rng).unwrap();
+		assert!(reply.chars().count() <= MAX_MESSAGE, "{}", reply.chars().count());
+		assert!(reply.ends_with("**tied at 400; nobody wins**"));
+		assert!(reply.contains("\n<@1>: 400\n"));
+
+		//	One side too long to show on its own
+		let reply = contest(&a, &test_side(2, "399"), TieRule::Draw, 100, &mut rng).unwrap();
+		assert!(reply.contains("<@1>: 400 (too many dice to show)\n<@2>: 399 = 399\n"), "{}", reply);
+	}
+}
@@ -0,0 +1,313 @@
+//		Imports
+use std::collections::{BTreeMap, VecDeque};
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+use twilight_model::{
+	gateway::payload::incoming::MessageCreate,
+	id::{Id, marker::ChannelMarker}
+};
+
+use crate::{
+	BotResult,
+	InteractionContext,
+	data::{ChannelData, GuildData}
+};
+
+use super::{
+	RollLimits, RollRecord,
+	history, is_manager, roll_with,
+	resolve::Scope
+};
+
+//		Data
+//	Bigger dice aren't kept for audits; there'd never be enough rolls to fill them
+const MAX_AUDIT_SIDES: i32 = 100;
+
+//		Functions
+pub(super) fn new_seed() -> u64 {
+	rand::thread_rng().gen()
+}
+
+//	The faces a roll keeps in the history for "audit".
+pub(super) fn audited(mut faces: BTreeMap<i32, Vec<i32>>) -> BTreeMap<i32, Vec<i32>> {
+	faces.retain(|&sides, _| sides <= MAX_AUDIT_SIDES);
+	faces
+}
+
+//	Picks the seed for the channel's next fair roll if there isn't one yet, and gives
+//	its commitment.
+async fn commit(channel_id: Id<ChannelMarker>) -> BotResult<String> {
+	let _lock = ChannelData::lock(channel_id).await;
+	let mut channel = history::load(channel_id).await?;
+	let seed = *channel.next_seed.get_or_insert_with(new_seed);
+	channel.write_file().await?;
+
+	Ok(commitment(channel_id, seed))
+}
+
+//	The hash a seed is committed to. The channel is mixed in so a commitment can't be
+//	passed off as another channel's.
+pub(super) fn commitment(channel_id: Id<ChannelMarker>, seed: u64) -> String {
+	hash(&format!("{}:{}", channel_id, seed))
+}
+
+//	Every face of a roll, by die size, boiled down to compare a replay against.
+pub(super) fn fingerprint(faces: &BTreeMap<i32, Vec<i32>>) -> String {
+	hash(&format!("{:?}", faces))
+}
+
+fn hash(text: &str) -> String {
+	Sha256::digest(text).iter()
+		.map(|byte| format!("{:02x}", byte))
+		.collect()
+}
+
+//	"fair" shows whether fair rolls are on, along with this channel's commitment for
+//	its next roll. "fair on|off" changes it.
+pub(super) async fn fair_reply(
+	ctx: &InteractionContext,
+	msg: &MessageCreate,
+	guild_data: Option<&mut GuildData>,
+	args: &str
+) -> BotResult<String> {
+	let Some(data) = guild_data else {
+		return Ok("fair rolls can only be turned on in a server".to_string())
+	};
+	let enabled = match args.trim() {
+		"" => {
+			if !data.fair_rolls { return Ok("fair rolls are off".to_string()) }
+
+			return Ok(format!("fair rolls are on; the next roll here is committed to `{}`", commit(msg.channel_id).await?))
+		}
+		"on" => true,
+		"off" => false,
+		_ => return Ok("usage: fair [on|off]".to_string())
+	};
+
+	if !is_manager(ctx, msg, data).await? {
+		return Ok("only server managers can turn fair rolls on or off".to_string())
+	}
+	data.fair_rolls = enabled;
+	data.write_file().await?;
+
+	//	The first roll needs something to have been committed to as well
+	Ok(match enabled {
+		true => format!("fair rolls are now on; the next roll here is committed to `{}`", commit(msg.channel_id).await?),
+		false => "fair rolls are now off".to_string()
+	})
+}
+
+//	"verify <id>" reveals the seed behind a fair roll, checks it against the commitment
+//	and rolls it again to show the same dice come up.
+pub(super) fn verify_reply(
+	history: &VecDeque<RollRecord>,
+	channel_id: Id<ChannelMarker>,
+	args: &str,
+	limits: &RollLimits,
+	scope: &Scope
+) -> String {
+	let Ok(id) = args.trim().trim_start_matches('#').parse::<u64>() else {
+		return "usage: verify <roll number>".to_string()
+	};
+	let Some(record) = history.iter().find(|record| record.id == id) else {
+		return format!("roll #{} isn't in this channel's recent history", id)
+	};
+	let Some(seed) = record.seed else {
+		return format!("roll #{} wasn't a fair roll, so there's no seed to check", id)
+	};
+
+	//	Rolls from before fingerprints were kept only have their audited faces
+	let same = |faces: BTreeMap<i32, Vec<i32>>| match &record.fingerprint {
+		Some(kept) => fingerprint(&faces) == *kept,
+		None => audited(faces) == record.faces
+	};
+	let replay = match roll_with(&record.input, limits, scope, None, Some(seed)).map(|rolled| same(rolled.faces)) {
+		Ok(true) => "rolling it again with that seed gives the same dice".to_string(),
+		Ok(_) => "rolling it again gives different dice; its macros or variables may have changed since".to_string(),
+		Err(e) => format!("it can't be rolled again: {}", e)
+	};
+
+	format!(
+		"roll #{} (<@{}>, `{}`) used seed {}, which hashes to `{}`\n{}",
+		id, record.user, record.input, seed, commitment(channel_id, seed), replay
+	)
+}
+
+//	"audit [dN]", a chi-square test of every dN face in the channel's history. Without
+//	a size, it picks whichever die has been rolled most.
+pub(super) fn audit_reply(history: &VecDeque<RollRecord>, args: &str) -> String {
+	let sides = match args.trim().trim_start_matches('d') {
+		"" => {
+			let mut totals: Vec<(i32, usize)> = vec![];
+			//	A d1 has nothing to test
+			for (&sides, faces) in history.iter().flat_map(|record| record.faces.iter()).filter(|(&sides, _)| sides >= 2) {
+				match totals.iter_mut().find(|(s, _)| *s == sides) {
+					Some((_, count)) => *count += faces.len(),
+					None => totals.push((sides, faces.len()))
+				}
+			}
+			match totals.iter().max_by_key(|(_, count)| *count) {
+				Some(&(sides, _)) => sides,
+				None => return "there are no rolls here to audit".to_string()
+			}
+		}
+		n => match n.parse::<i32>() {
+			Ok(n) if (2..=MAX_AUDIT_SIDES).contains(&n) => n,
+			_ => return format!("usage: audit [dN], for dice of 2 to {} sides", MAX_AUDIT_SIDES)
+		}
+	};
+
+	let mut counts = vec![0u32; sides as usize];
+	let mut rolls = 0;
+	for record in history {
+		let Some(faces) = record.faces.get(&sides) else { continue };
+		rolls += 1;
+		for &face in faces {
+			if let Some(count) = (face as usize).checked_sub(1).and_then(|i| counts.get_mut(i)) { *count += 1; }
+		}
+	}
+
+	let total: u32 = counts.iter().sum();
+	if total == 0 { return format!("there are no d{} rolls here to audit", sides) }
+
+	let (chi2, p) = chi_square(&counts);
+	let verdict = match p {
+		p if p < 0.001 => "very unlikely from a fair die",
+		p if p < 0.05 => "a little unusual, though one audit in twenty will say so by chance",
+		_ => "consistent with a fair die"
+	};
+	let tally: Vec<String> = counts.iter().enumerate()
+		.map(|(face, count)| format!("{}: {}", face + 1, count))
+		.collect();
+
+	let mut reply = format!(
+		"**d{} audit**: {} faces from {} rolls here\n{}\nχ² = {:.2} on {} degrees of freedom, p = {:.3}: {}",
+		sides, total, rolls, tally.join(" · "), chi2, sides - 1, p, verdict
+	);
+	//	The test isn't worth much until every face is expected a handful of times
+	if (total as f64 / sides as f64) < 5.0 {
+		reply += "\n(too few rolls for this to mean much yet)";
+	}
+
+	reply
+}
+
+//	Pearson's statistic against a uniform die, and how likely one at least that large
+//	is from a fair one. The tail uses the Wilson-Hilferty approximation, which is plenty
+//	for deciding whether to worry.
+fn chi_square(counts: &[u32]) -> (f64, f64) {
+	let total: u32 = counts.iter().sum();
+	let expected = total as f64 / counts.len() as f64;
+	let chi2: f64 = counts.iter()
+		.map(|&count| (count as f64 - expected).powi(2) / expected)
+		.sum();
+
+	let df = (counts.len() - 1) as f64;
+	let spread = 2.0 / (9.0 * df);
+	let z = ((chi2 / df).cbrt() - (1.0 - spread)) / spread.sqrt();
+
+	(chi2, 0.5 * erfc(z / std::f64::consts::SQRT_2))
+}
+
+//	Complementary error function, to within about 1e-7 (Numerical Recipes' erfcc).
+fn erfc(x: f64) -> f64 {
+	let t = 1.0 / (1.0 + 0.5 * x.abs());
+	let poly = -x * x - 1.265_512_23 + t * (1.000_023_68 + t * (0.374_091_96 + t * (0.096_784_18
+		+ t * (-0.186_288_06 + t * (0.278_868_07 + t * (-1.135_203_98 + t * (1.488_515_87
+		+ t * (-0.822_152_23 + t * 0.170_872_77))))))));
+	let ans = t * poly.exp();
+
+	if x >= 0.0 { ans } else { 2.0 - ans }
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn commitments() {
+		let hash = commitment(Id::new(5), 42);
+		assert_eq!(hash.len(), 64);
+		assert_eq!(hash, commitment(Id::new(5), 42));
+		assert_ne!(hash, commitment(Id::new(6), 42));
+		assert_ne!(hash, commitment(Id::new(5), 43));
+	}
+
+	#[test]
+	fn chi_squares() {
+		let (chi2, p) = chi_square(&[10; 20]);
+		assert_eq!(chi2, 0.0);
+		assert!(p > 0.99);
+
+		//	A d6 that loves sixes
+		let (_, p) = chi_square(&[10, 10, 10, 10, 10, 60]);
+		assert!(p < 0.001);
+
+		//	Close to the exact tail: P(χ² ≥ 12.8) on 5 degrees of freedom is 0.0253
+		let (chi2, p) = chi_square(&[2, 18, 10, 10, 10, 10]);
+		assert!((chi2 - 12.8).abs() < 1e-9);
+		assert!((p - 0.0253).abs() < 0.002, "{}", p);
+	}
+
+	#[test]
+	fn audits() {
+		let mut history = VecDeque::new();
+		for i in 0..30 {
+			let mut record = RollRecord::new(i, Id::new(1), "1d6", "you rolled: 1");
+			record.faces.insert(6, vec![(i % 6) as i32 + 1]);
+			history.push_back(record);
+		}
+
+		let reply = audit_reply(&history, "");
+		assert!(reply.starts_with("**d6 audit**: 30 faces from 30 rolls"), "{}", reply);
+		assert!(reply.contains("consistent with a fair die"));
+		assert_eq!(audit_reply(&history, "d6"), reply);
+		assert_eq!(audit_reply(&history, "d20"), "there are no d20 rolls here to audit");
+		assert_eq!(audit_reply(&VecDeque::new(), ""), "there are no rolls here to audit");
+
+		//	Plenty of d1s, but there's nothing to test in them
+		for i in 0..40 {
+			let mut record = RollRecord::new(30 + i, Id::new(1), "1d1", "you rolled: 1");
+			record.faces.insert(1, vec![1]);
+			history.push_back(record);
+		}
+		assert_eq!(audit_reply(&history, ""), reply);
+	}
+
+	#[test]
+	fn verifying() {
+		let (limits, scope) = (RollLimits::default(), Scope::default());
+		let mut history = VecDeque::new();
+
+		//	Dice too big to audit still have to replay the same
+		let input = "1d1000 + 2d6";
+		let rolled = roll_with(input, &limits, &scope, None, Some(42)).unwrap();
+		let mut record = RollRecord::new(1, Id::new(1), input, &rolled.text);
+		record.fingerprint = Some(fingerprint(&rolled.faces));
+		record.faces = audited(rolled.faces);
+		record.seed = Some(42);
+		history.push_back(record);
+
+		let reply = verify_reply(&history, Id::new(5), "#1", &limits, &scope);
+		assert!(reply.ends_with("rolling it again with that seed gives the same dice"), "{}", reply);
+		assert!(reply.contains(&commitment(Id::new(5), 42)));
+
+		history[0].seed = Some(43);
+		assert!(verify_reply(&history, Id::new(5), "1", &limits, &scope).contains("different dice"));
+		assert_eq!(verify_reply(&history, Id::new(5), "2", &limits, &scope), "roll #2 isn't in this channel's recent history");
+
+		//	Nothing of a lone d1000 is audited, but its replay is still checked
+		let rolled = roll_with("1d1000 + 5", &limits, &scope, None, Some(42)).unwrap();
+		let mut record = RollRecord::new(2, Id::new(1), "1d1000 + 5", &rolled.text);
+		record.fingerprint = Some(fingerprint(&rolled.faces));
+		record.faces = audited(rolled.faces);
+		record.seed = Some(43);
+		assert!(record.faces.is_empty());
+		history.push_back(record);
+		assert!(verify_reply(&history, Id::new(5), "2", &limits, &scope).contains("different dice"));
+
+		history[1].seed = Some(42);
+		assert!(verify_reply(&history, Id::new(5), "2", &limits, &scope).ends_with("gives the same dice"));
+	}
+}
@@ -0,0 +1,175 @@
+//		Imports
+use std::fmt;
+use serde::{Deserialize, Serialize};
+
+use twilight_model::{
+	gateway::payload::incoming::MessageCreate,
+	id::{
+		Id, marker::{
+			GuildMarker,
+			RoleMarker,
+			UserMarker
+		}
+	}
+};
+
+use crate::{
+	BotResult,
+	InteractionContext,
+	data::{GuildData, UserData}
+};
+
+use super::{
+	MAX_MESSAGE, RollLimits,
+	is_manager, roll_reply, scope, truncate,
+	system::system_for
+};
+
+//		Data
+//	Who secret rolls are sent to: one user, or everyone with a role.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMaster {
+	User(Id<UserMarker>),
+	Role(Id<RoleMarker>),
+}
+
+//	Written as a mention
+impl fmt::Display for GameMaster {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			GameMaster::User(id) => write!(f, "<@{}>", id),
+			GameMaster::Role(id) => write!(f, "<@&{}>", id),
+		}
+	}
+}
+
+//		Functions
+impl GameMaster {
+	//	Reads a user or role mention, "<@123>", "<@!123>" or "<@&123>".
+	pub fn from_mention(s: &str) -> Option<Self> {
+		let inner = s.strip_prefix("<@")?.strip_suffix('>')?;
+		match inner.strip_prefix('&') {
+			Some(id) => Some(GameMaster::Role(Id::new_checked(id.parse().ok()?)?)),
+			None => Some(GameMaster::User(Id::new_checked(inner.trim_start_matches('!').parse().ok()?)?)),
+		}
+	}
+
+	pub fn includes(&self, user_id: Id<UserMarker>, roles: &[Id<RoleMarker>]) -> bool {
+		match self {
+			GameMaster::User(id) => *id == user_id,
+			GameMaster::Role(id) => roles.contains(id),
+		}
+	}
+
+	//	DMs the GM, or everyone with the GM role. Listing a role's members needs the
+	//	Server Members intent turned on for the bot.
+	pub async fn send(
+		&self,
+		ctx: &InteractionContext,
+		guild_id: Id<GuildMarker>,
+		content: &str
+	) -> BotResult<()> {
+		let users: Vec<Id<UserMarker>> = match *self {
+			GameMaster::User(id) => vec![id],
+			GameMaster::Role(role_id) => ctx.http.guild_members(guild_id).limit(1000)?.await?
+				.models().await?
+				.into_iter()
+				.filter(|member| member.roles.contains(&role_id))
+				.map(|member| member.user.id)
+				.collect()
+		};
+
+		for user_id in users {
+			send_dm(ctx, user_id, content).await?;
+		}
+
+		Ok(())
+	}
+}
+
+pub async fn send_dm(ctx: &InteractionContext, user_id: Id<UserMarker>, content: &str) -> BotResult<()> {
+	let channel = ctx.http.create_private_channel(user_id).await?.model().await?;
+	ctx.http.create_message(channel.id).content(content)?.await?;
+
+	Ok(())
+}
+
+//	"gm" shows who gets secret rolls and "gm set <@user|@role>" or "gm clear" change
+//	it. Anything else is a secret roll: the GM gets the breakdown, as does the roller
+//	if they aren't the GM, and the channel only hears that it happened.
+pub(super) async fn gm_reply(
+	ctx: &InteractionContext,
+	msg: &MessageCreate,
+	guild_data: Option<&mut GuildData>,
+	user_data: &UserData,
+	args: &str,
+	limits: &RollLimits
+) -> BotResult<String> {
+	let Some(data) = guild_data else {
+		return Ok("secret rolls need a server with a GM".to_string())
+	};
+
+	let words: Vec<&str> = args.split_whitespace().collect();
+	let change = match words[..] {
+		[] => return Ok(match data.gm {
+			Some(gm) => format!("secret rolls go to {}", gm),
+			None => "no GM is set; a server manager can set one with `!dice gm set @user`".to_string()
+		}),
+		["set", mention] => match GameMaster::from_mention(mention) {
+			Some(gm) => Some(Some(gm)),
+			None => return Ok("usage: gm set <@user|@role>".to_string())
+		},
+		["clear"] => Some(None),
+		_ => None
+	};
+
+	if let Some(gm) = change {
+		if !is_manager(ctx, msg, data).await? {
+			return Ok("only server managers can change the GM".to_string())
+		}
+		data.gm = gm;
+		data.write_file().await?;
+
+		return Ok(match gm {
+			Some(gm) => format!("secret rolls now go to {}", gm),
+			None => "secret rolls are off until a GM is set".to_string()
+		})
+	}
+
+	let Some(gm) = data.gm else {
+		return Ok("no GM is set; a server manager can set one with `!dice gm set @user`".to_string())
+	};
+	let system = system_for(Some(msg.channel_id), Some(data)).await?;
+	let roll = match roll_reply(args, limits, &scope(user_data, Some(data)), system) {
+		Ok(roll) => roll,
+		Err(e) => return Ok(e)
+	};
+
+	//	The mentions can push a roll at max_output over what a DM holds
+	let secret = truncate(&format!("<@{}> rolled in <#{}>: {}", msg.author.id, msg.channel_id, roll), MAX_MESSAGE);
+	gm.send(ctx, data.id, &secret).await?;
+
+	let roles = msg.member.as_ref().map(|member| member.roles.clone()).unwrap_or_default();
+	if !gm.includes(msg.author.id, &roles) {
+		send_dm(ctx, msg.author.id, &secret).await?;
+	}
+
+	Ok("a secret roll was made".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn mentions() {
+		assert_eq!(GameMaster::from_mention("<@42>"), Some(GameMaster::User(Id::new(42))));
+		assert_eq!(GameMaster::from_mention("<@!42>"), Some(GameMaster::User(Id::new(42))));
+		assert_eq!(GameMaster::from_mention("<@&7>"), Some(GameMaster::Role(Id::new(7))));
+		assert_eq!(GameMaster::from_mention("<@0>"), None);
+		assert_eq!(GameMaster::from_mention("@gm"), None);
+		assert_eq!(GameMaster::from_mention("<#42>"), None);
+
+		assert_eq!(GameMaster::Role(Id::new(7)).to_string(), "<@&7>");
+	}
+}
@@ -0,0 +1,177 @@
+//		Imports
+use std::collections::{BTreeMap, VecDeque};
+use serde::{Deserialize, Serialize};
+
+use twilight_model::id::{
+	Id, marker::{
+		ChannelMarker,
+		UserMarker
+	}
+};
+
+use crate::{
+	BotResult,
+	data::ChannelData
+};
+
+use super::{MAX_MESSAGE, truncate};
+
+//		Data
+//	How many rolls each channel remembers
+const MAX_HISTORY: usize = 50;
+
+//	How much of each roll "history" shows; "last" shows as much as fits in a message
+const SUMMARY_WIDTH: usize = 150;
+//	How much of what was asked for either one shows
+const INPUT_WIDTH: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RollRecord {
+	//	Counts up from 1 in each channel
+	#[serde(default)]
+	pub id:u64,
+	pub user:Id<UserMarker>,
+	//	What was asked for, with a macro name written as "$name"
+	pub input:String,
+	//	The reply, breakdown and all
+	pub result:String,
+	//	Unix seconds
+	pub time:i64,
+
+	//	Every face rolled, by die size, for "audit"
+	#[serde(default)]
+	pub faces:BTreeMap<i32, Vec<i32>>,
+	//	The committed seed of a fair roll, revealed by "verify"
+	#[serde(default)]
+	pub seed:Option<u64>,
+	//	A hash of every face a fair roll rolled, big dice and all, for "verify" to
+	//	compare a replay against
+	#[serde(default)]
+	pub fingerprint:Option<String>
+}
+
+//		Functions
+pub(super) async fn load(channel_id: Id<ChannelMarker>) -> BotResult<ChannelData> {
+	ChannelData::read_or_new(channel_id).await
+}
+
+impl RollRecord {
+	pub fn new(id: u64, user: Id<UserMarker>, input: &str, result: &str) -> Self {
+		RollRecord{
+			id,
+			user,
+			input: input.to_owned(),
+			result: result.to_owned(),
+			time: time::OffsetDateTime::now_utc().unix_timestamp(),
+			faces: BTreeMap::new(),
+			seed: None,
+			fingerprint: None
+		}
+	}
+
+	//	"<when> <who> `input` → result", cut down to `width` characters of result.
+	fn line(&self, width: usize) -> String {
+		let result = self.result.strip_prefix("you rolled").unwrap_or(&self.result)
+			.trim_start_matches(':').trim();
+
+		format!(
+			"<t:{}:R> <@{}> `{}` → {}",
+			self.time, self.user, truncate(&self.input, INPUT_WIDTH), truncate(result, width)
+		)
+	}
+}
+
+//	Adds a roll, forgetting the oldest once there are too many.
+pub(super) fn push(history: &mut VecDeque<RollRecord>, record: RollRecord) {
+	history.push_back(record);
+	while history.len() > MAX_HISTORY {
+		history.pop_front();
+	}
+}
+
+//	"history [n]", the last n rolls in the channel, newest last.
+pub(super) fn history_reply(history: &VecDeque<RollRecord>, args: &str) -> String {
+	let count = match args.trim() {
+		"" => 10,
+		n => match n.parse::<usize>() {
+			Ok(n) => n.clamp(1, MAX_HISTORY),
+			Err(_) => return "usage: history [count]".to_string()
+		}
+	};
+	if history.is_empty() { return "nobody has rolled here yet".to_string() }
+
+	//	Newest first until the message is full, then back into order
+	let mut lines: Vec<String> = vec![];
+	let mut length = 0;
+	for record in history.iter().rev().take(count) {
+		let line = record.line(SUMMARY_WIDTH);
+		length += line.chars().count() + 1;
+		if length > MAX_MESSAGE { break }
+		lines.push(line);
+	}
+	lines.reverse();
+
+	lines.join("\n")
+}
+
+pub(super) fn last_reply(history: &VecDeque<RollRecord>) -> String {
+	match history.back() {
+		Some(record) => truncate(&record.line(usize::MAX), MAX_MESSAGE),
+		None => "nobody has rolled here yet".to_string()
+	}
+}
+
+//	What `user` last rolled here, for "reroll".
+pub(super) fn last_input(history: &VecDeque<RollRecord>, user: Id<UserMarker>) -> Option<&str> {
+	history.iter().rev()
+		.find(|record| record.user == user)
+		.map(|record| record.input.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn record(user: u64, input: &str, result: &str) -> RollRecord {
+		RollRecord{ time: 1_700_000_000, ..RollRecord::new(0, Id::new(user), input, result) }
+	}
+
+	#[test]
+	fn bounded() {
+		let mut history = VecDeque::new();
+		for i in 0..MAX_HISTORY + 5 {
+			push(&mut history, record(1, &format!("1d{}", i + 1), "you rolled: 1"));
+		}
+
+		assert_eq!(history.len(), MAX_HISTORY);
+		assert_eq!(history.front().unwrap().input, "1d6");
+	}
+
+	#[test]
+	fn recall() {
+		let mut history = VecDeque::new();
+		push(&mut history, record(1, "1d20 + 5", "you rolled: 1d20 [12] + 5 = 17"));
+		push(&mut history, record(2, "$attack", "you rolled: 1d20 [3] + 7 = 10"));
+
+		assert_eq!(last_input(&history, Id::new(1)), Some("1d20 + 5"));
+		assert_eq!(last_input(&history, Id::new(3)), None);
+		assert_eq!(last_reply(&history), "<t:1700000000:R> <@2> `$attack` → 1d20 [3] + 7 = 10");
+
+		assert_eq!(history_reply(&history, "1").lines().count(), 1);
+		assert_eq!(history_reply(&history, "").lines().count(), 2);
+		assert_eq!(history_reply(&VecDeque::new(), ""), "nobody has rolled here yet");
+	}
+
+	#[test]
+	fn fits_a_message() {
+		let mut history = VecDeque::new();
+		for _ in 0..MAX_HISTORY {
+			push(&mut history, record(1, &"1d20 + ".repeat(40), &format!("you rolled: {}", "1d20 [20] + ".repeat(200))));
+		}
+
+		let reply = history_reply(&history, "50");
+		assert!(reply.chars().count() <= MAX_MESSAGE);
+		assert!(reply.lines().count() > 1);
+		assert!(last_reply(&history).chars().count() <= MAX_MESSAGE);
+	}
+}
@@ -0,0 +1,1019 @@
+//		Imports
+use std::{
+	collections::{BTreeMap, HashMap},
+	fmt,
+	str::FromStr,
+};
+use rand::{RngCore, SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+use tokio::sync::OwnedMutexGuard;
+
+use twilight_model::{
+	application::interaction::Interaction,
+	channel::message::AllowedMentions,
+	gateway::payload::incoming::MessageCreate,
+	id::{
+		Id, marker::{
+			ChannelMarker,
+			GuildMarker,
+			UserMarker
+		}
+	}
+};
+
+use crate::{
+	BotResult,
+	InteractionContext,
+	data::{ChannelData, GuildData, UserData},
+	permissions::can_manage_guild
+};
+
+mod contest;
+mod fair;
+mod gm;
+mod history;
+mod parse;
+mod resolve;
+mod roll;
+mod stats;
+mod system;
+
+pub use contest::TieRule;
+pub use gm::{GameMaster, send_dm};
+pub use history::RollRecord;
+pub use system::GameSystem;
+use resolve::{ResolveError, Scope};
+use roll::{RollError, RollResult};
+
+//	Words that can't be used as macro names, since "dice <name>" rolls a macro
+const SUBCOMMANDS: [&str; 15] = [
+	"stats", "limits", "save", "forget", "macros", "inline", "gm", "history", "last", "reroll", "system",
+	"fair", "verify", "audit", "vs"
+];
+//	The subcommands that can change the guild's or user's data
+const WRITERS: [&str; 8] = ["limits", "save", "forget", "inline", "gm", "system", "fair", "vs"];
+
+//		Command
+pub async fn dice(
+	ctx: InteractionContext,
+	msg: Box<MessageCreate>,
+	rest: &str
+) -> BotResult<()> {
+	//	Subcommands come first, then macro names, anything else is an expression to roll
+	let (sub, args) = rest.split_once(' ').unwrap_or((rest, ""));
+
+	//	Held from loading the data to writing it back, so changes can't cross
+	let _locks = match WRITERS.contains(&sub) {
+		true => Some(lock(msg.guild_id, msg.author.id).await),
+		false => None
+	};
+	//	Limits are per guild; DMs get the defaults
+	let (mut guild_data, mut user_data) = load(msg.guild_id, msg.author.id).await?;
+	let limits = guild_data.as_ref().map(|data| data.roll_limits.clone()).unwrap_or_default();
+
+	let reply: String = match sub {
+		"stats" => stats_reply(args.trim(), &limits, &scope(&user_data, guild_data.as_ref())).await?,
+		"limits" => limits_reply(&ctx, &msg, guild_data.as_mut(), args).await?,
+		"save" => save_reply(&ctx, &msg, &mut user_data, guild_data.as_mut(), args, &limits).await?,
+		"forget" => forget_reply(&ctx, &msg, &mut user_data, guild_data.as_mut(), args).await?,
+		"macros" => macros_reply(&user_data, guild_data.as_ref()),
+		"inline" => inline_toggle_reply(&ctx, &msg, guild_data.as_mut(), args).await?,
+		"gm" => gm::gm_reply(&ctx, &msg, guild_data.as_mut(), &user_data, args, &limits).await?,
+		"system" => system::system_reply(&ctx, &msg, guild_data.as_mut(), args).await?,
+		"fair" => fair::fair_reply(&ctx, &msg, guild_data.as_mut(), args).await?,
+		"verify" => {
+			let history = history::load(msg.channel_id).await?.history;
+			fair::verify_reply(&history, msg.channel_id, args, &limits, &scope(&user_data, guild_data.as_ref()))
+		}
+		"audit" => fair::audit_reply(&history::load(msg.channel_id).await?.history, args),
+		"vs" => contest::vs_reply(&ctx, &msg, guild_data.as_mut(), &user_data, args, &limits).await?,
+		"history" => history::history_reply(&history::load(msg.channel_id).await?.history, args),
+		"last" => history::last_reply(&history::load(msg.channel_id).await?.history),
+		"reroll" => {
+			let channel = history::load(msg.channel_id).await?;
+			match history::last_input(&channel.history, msg.author.id) {
+				//	A seed would only replay the same dice
+				Some(input) => {
+					let (input, comment) = parse::split_comment(input);
+					let input = take_option(input, "--seed").map(|(input, _)| input).unwrap_or(input.to_owned());
+					let input = format!("{} {}", input, comment).trim_end().to_owned();
+					let scope = scope(&user_data, guild_data.as_ref());
+					let system = system::system_for(Some(msg.channel_id), guild_data.as_ref()).await?;
+					let fair = guild_data.as_ref().is_some_and(|data| data.fair_rolls);
+					roll_and_record(msg.channel_id, msg.author.id, &input, &limits, &scope, system, fair).await?
+				}
+				None => "you haven't rolled here yet".to_string()
+			}
+		}
+		_ => {
+			let scope = scope(&user_data, guild_data.as_ref());
+			let rest = match scope.find_macro(sub) {
+				Some(_) => format!("${} {}", sub, args),
+				None => rest.to_owned()
+			};
+			let system = system::system_for(Some(msg.channel_id), guild_data.as_ref()).await?;
+			let fair = guild_data.as_ref().is_some_and(|data| data.fair_rolls);
+			roll_and_record(msg.channel_id, msg.author.id, &rest, &limits, &scope, system, fair).await?
+		}
+	};
+
+	//	Replies mention people, but shouldn't ping them
+	ctx.http.create_message(msg.channel_id)
+		.allowed_mentions(Some(&AllowedMentions::default()))
+		.content(&truncate(&reply, MAX_MESSAGE))?
+		.await?;
+
+	Ok(())
+}
+
+//	Rolls every [[expr]] in an ordinary message, the way Roll20 does, if the guild has
+//	them turned on. They're always on in DMs.
+pub async fn inline(
+	ctx: InteractionContext,
+	msg: Box<MessageCreate>
+) -> BotResult<()> {
+	//	Cheap checks first, since this sees every message
+	if msg.author.bot { return Ok(()) }
+	let segments = parse::inline_rolls(&msg.content);
+	if segments.is_empty() { return Ok(()) }
+
+	let (guild_data, user_data) = load(msg.guild_id, msg.author.id).await?;
+	if guild_data.as_ref().is_some_and(|data| !data.inline_rolls) { return Ok(()) }
+
+	let limits = guild_data.as_ref().map(|data| data.roll_limits.clone()).unwrap_or_default();
+	let scope = scope(&user_data, guild_data.as_ref());
+	let system = system::system_for(Some(msg.channel_id), guild_data.as_ref()).await?;
+	let shown = segments.len().min(limits.max_repeats as usize);
+	let width = limits.max_output / shown;
+
+	let lines: Vec<String> = segments.iter()
+		.take(shown)
+		.map(|expr| {
+			let roll = DiceCommand::parse_with(expr, &limits)
+				.map_err(|e| e.to_string())
+				.and_then(|command| command.resolve(&scope, &limits).map_err(|e| e.to_string()))
+				.and_then(|command| command.roll(&mut rand::thread_rng()).map_err(|e| e.to_string()));
+
+			match roll {
+				Ok(roll) => {
+					let note = outcome(system, &roll, None);
+					match format!("`{}` → {}{}", expr, roll, note) {
+						line if line.chars().count() > width => format!("`{}` → {}{}", expr, roll.total, note),
+						line => line
+					}
+				}
+				//	Without the caret under the input if that doesn't fit either
+				Err(e) => match format!("`{}` → {}", expr, e) {
+					line if line.chars().count() > width => {
+						let e = e.lines().next().unwrap_or_default();
+						truncate(&format!("`{}` → {}", expr, e), width)
+					}
+					line => line
+				}
+			}
+		})
+		.collect();
+
+	ctx.http.create_message(msg.channel_id)
+		.reply(msg.id)
+		.allowed_mentions(Some(&AllowedMentions::default()))
+		.content(&truncate(&lines.join("\n"), MAX_MESSAGE))?
+		.await?;
+
+	Ok(())
+}
+
+//	The "/roll expr hidden" slash command. Returns the response, and whether only the
+//	roller should see it - hidden rolls also go to the GM & leave a note in the channel.
+pub async fn roll_command(
+	ctx: &InteractionContext,
+	interaction: &Interaction,
+	expr: &str,
+	hidden: bool
+) -> BotResult<(String, bool)> {
+	let user_id = interaction.author_id().ok_or("Interaction has no author")?;
+	let (guild_data, user_data) = load(interaction.guild_id, user_id).await?;
+
+	let limits = guild_data.as_ref().map(|data| data.roll_limits.clone()).unwrap_or_default();
+	let channel_id = interaction.channel.as_ref().map(|channel| channel.id);
+	let scope = scope(&user_data, guild_data.as_ref());
+	let system = system::system_for(channel_id, guild_data.as_ref()).await?;
+	if !hidden {
+		let fair = guild_data.as_ref().is_some_and(|data| data.fair_rolls);
+		return Ok(match channel_id {
+			Some(channel_id) => (roll_and_record(channel_id, user_id, expr, &limits, &scope, system, fair).await?, false),
+			None => roll_reply(expr, &limits, &scope, system).map(|roll| (roll, false)).unwrap_or_else(|e| (e, true))
+		})
+	}
+
+	let roll = match roll_reply(expr, &limits, &scope, system) {
+		Ok(roll) => roll,
+		Err(e) => return Ok((e, true))
+	};
+
+	//	Hidden rolls still reach the GM if there is one, but stay out of the history
+	let gm = guild_data.as_ref().and_then(|data| data.gm.map(|gm| (data.id, gm)));
+	if let Some((guild_id, gm)) = gm {
+		let roles = interaction.member.as_ref().map(|member| member.roles.clone()).unwrap_or_default();
+		if !gm.includes(user_id, &roles) {
+			let channel = channel_id.map(|id| format!(" in <#{}>", id)).unwrap_or_default();
+			let secret = truncate(&format!("<@{}> rolled{}: {}", user_id, channel, roll), MAX_MESSAGE);
+			gm.send(ctx, guild_id, &secret).await?;
+		}
+	}
+	if let Some(channel_id) = channel_id {
+		ctx.http.create_message(channel_id).content("a secret roll was made")?.await?;
+	}
+
+	Ok((roll, true))
+}
+
+//	Rolls `expr` for another command, with the roller's macros, variables & limits.
+//	Gives the total and the breakdown.
+pub async fn roll_for(
+	guild_id: Option<Id<GuildMarker>>,
+	user_id: Id<UserMarker>,
+	expr: &str
+) -> Result<(i32, String), String> {
+	let (guild_data, user_data) = load(guild_id, user_id).await.map_err(|e| e.to_string())?;
+	let limits = guild_data.as_ref().map(|data| data.roll_limits.clone()).unwrap_or_default();
+
+	let command = DiceCommand::parse_with(expr, &limits)
+		.map_err(|e| e.to_string())?
+		.resolve(&scope(&user_data, guild_data.as_ref()), &limits)
+		.map_err(|e| e.to_string())?;
+	let roll = command.roll(&mut rand::thread_rng()).map_err(|e| e.to_string())?;
+
+	Ok((roll.total, roll.to_string()))
+}
+
+//	The guild's data (outside of DMs) and the user's, made fresh if they're missing.
+async fn load(guild_id: Option<Id<GuildMarker>>, user_id: Id<UserMarker>) -> BotResult<(Option<GuildData>, UserData)> {
+	let guild_data: Option<GuildData> = match guild_id {
+		Some(guild_id) => Some(GuildData::read_or_new(guild_id).await?),
+		None => None
+	};
+	let user_data = UserData::read_or_new(user_id).await?;
+
+	Ok((guild_data, user_data))
+}
+
+//	The guild's lock (outside of DMs) and the user's, for changing their data.
+async fn lock(guild_id: Option<Id<GuildMarker>>, user_id: Id<UserMarker>) -> (Option<OwnedMutexGuard<()>>, OwnedMutexGuard<()>) {
+	let guild = match guild_id {
+		Some(guild_id) => Some(GuildData::lock(guild_id).await),
+		None => None
+	};
+
+	(guild, UserData::lock(user_id).await)
+}
+
+async fn is_manager(ctx: &InteractionContext, msg: &MessageCreate, data: &GuildData) -> BotResult<bool> {
+	let roles = msg.member.as_ref().map(|member| member.roles.clone()).unwrap_or_default();
+	can_manage_guild(ctx, data.id, msg.author.id, &roles).await
+}
+
+//	"inline on|off"
+async fn inline_toggle_reply(
+	ctx: &InteractionContext,
+	msg: &MessageCreate,
+	guild_data: Option<&mut GuildData>,
+	args: &str
+) -> BotResult<String> {
+	let Some(data) = guild_data else {
+		return Ok("inline rolls are always on in DMs".to_string())
+	};
+	let enabled = match args.trim() {
+		"" => return Ok(format!("inline rolls are {}", if data.inline_rolls { "on" } else { "off" })),
+		"on" => true,
+		"off" => false,
+		_ => return Ok("usage: inline [on|off]".to_string())
+	};
+
+	if !is_manager(ctx, msg, data).await? {
+		return Ok("only server managers can turn inline rolls on or off".to_string())
+	}
+	data.inline_rolls = enabled;
+	data.write_file().await?;
+
+	Ok(format!("inline rolls are now {}", args.trim()))
+}
+
+//	"limits" shows the guild's roll limits, "limits <name> <value>" changes one.
+async fn limits_reply(
+	ctx: &InteractionContext,
+	msg: &MessageCreate,
+	guild_data: Option<&mut GuildData>,
+	args: &str
+) -> BotResult<String> {
+	let words: Vec<&str> = args.split_whitespace().collect();
+	let (name, value) = match words[..] {
+		[] => return Ok(guild_data.map(|data| data.roll_limits.to_string())
+			.unwrap_or_else(|| RollLimits::default().to_string())),
+		[name, value] => (name, value),
+		_ => return Ok("usage: limits [<name> <value>]".to_string())
+	};
+
+	let Some(data) = guild_data else {
+		return Ok("limits can only be changed in a server".to_string())
+	};
+	if !is_manager(ctx, msg, data).await? {
+		return Ok("only server managers can change the dice limits".to_string())
+	}
+
+	Ok(match data.roll_limits.set(name, value) {
+		Ok(()) => {
+			data.write_file().await?;
+			data.roll_limits.to_string()
+		}
+		Err(e) => e
+	})
+}
+
+//	A user's own macros shadow the guild's. Variables are kept per guild, since a
+//	user likely plays a different character in each.
+fn scope<'a>(user_data: &'a UserData, guild_data: Option<&'a GuildData>) -> Scope<'a> {
+	let mut macros = vec![&user_data.macros];
+	macros.extend(guild_data.map(|data| &data.macros));
+	let vars = guild_data.and_then(|data| user_data.vars.get(&data.id));
+
+	Scope{ macros, vars }
+}
+
+//	"save [--guild] <name> <expr>". Guild macros are for everyone, but only server
+//	managers can change them.
+async fn save_reply(
+	ctx: &InteractionContext,
+	msg: &MessageCreate,
+	user_data: &mut UserData,
+	guild_data: Option<&mut GuildData>,
+	args: &str,
+	limits: &RollLimits
+) -> BotResult<String> {
+	let (guild, args) = match args.trim().strip_prefix("--guild") {
+		Some(args) => (true, args.trim()),
+		None => (false, args.trim())
+	};
+	let Some((name, body)) = args.split_once(' ') else {
+		return Ok("usage: save [--guild] <name> <expr>".to_string())
+	};
+
+	if let Err(e) = check_macro_name(name) { return Ok(e) }
+	let body = match DiceCommand::parse_with(body.trim(), limits) {
+		Ok(body) => body,
+		Err(e) => return Ok(e.to_string())
+	};
+
+	//	Try it out with the new definition in place, which catches cycles & unknown names.
+	//	Guild macros can't lean on anyone's own macros.
+	let mut layer: HashMap<String, DiceCommand> = match (guild, guild_data.as_deref()) {
+		(true, Some(data)) => data.macros.clone(),
+		(true, None) => return Ok("guild macros can only be saved in a server".to_string()),
+		(false, _) => user_data.macros.clone()
+	};
+	layer.insert(name.to_owned(), body.clone());
+
+	let check = match (guild, guild_data.as_deref()) {
+		(true, _) => Scope{ macros: vec![&layer], vars: None },
+		(false, data) => {
+			let mut macros = vec![&layer];
+			macros.extend(data.map(|data| &data.macros));
+			Scope{ macros, vars: None }
+		}
+	};
+	//	Variables are filled in by whoever rolls, so they aren't needed yet
+	let reference = DiceCommand{ expr: Expr::Macro(name.to_owned()), max_explosions: limits.max_explosions, comment: None };
+	match reference.resolve(&check, limits) {
+		Ok(_) | Err(ResolveError::UnknownVar(_)) => {}
+		Err(e) => return Ok(e.to_string())
+	}
+
+	match (guild, guild_data) {
+		(true, Some(data)) => {
+			if !is_manager(ctx, msg, data).await? {
+				return Ok("only server managers can save guild macros".to_string())
+			}
+
+			data.macros.insert(name.to_owned(), body.clone());
+			data.write_file().await?;
+		}
+		_ => {
+			user_data.macros.insert(name.to_owned(), body.clone());
+			user_data.write_file().await?;
+		}
+	}
+
+	Ok(format!("saved ${}: {}", name, body))
+}
+
+//	"forget [--guild] <name>"
+async fn forget_reply(
+	ctx: &InteractionContext,
+	msg: &MessageCreate,
+	user_data: &mut UserData,
+	guild_data: Option<&mut GuildData>,
+	args: &str
+) -> BotResult<String> {
+	let (name, guild_data) = match args.trim().strip_prefix("--guild") {
+		Some(name) => match guild_data {
+			Some(data) => (name.trim(), Some(data)),
+			None => return Ok("guild macros can only be changed in a server".to_string())
+		},
+		None => (args.trim(), None)
+	};
+
+	match guild_data {
+		Some(data) => {
+			if !is_manager(ctx, msg, data).await? {
+				return Ok("only server managers can forget guild macros".to_string())
+			}
+			if data.macros.remove(name).is_none() {
+				return Ok(format!("there's no guild macro called ${}", name))
+			}
+			data.write_file().await?;
+		}
+		None => {
+			if user_data.macros.remove(name).is_none() {
+				return Ok(format!("you don't have a macro called ${}", name))
+			}
+			user_data.write_file().await?;
+		}
+	}
+
+	Ok(format!("forgot ${}", name))
+}
+
+fn macros_reply(user_data: &UserData, guild_data: Option<&GuildData>) -> String {
+	let list = |macros: &HashMap<String, DiceCommand>| {
+		let mut lines: Vec<String> = macros.iter()
+			.map(|(name, body)| format!("${}: {}", name, body))
+			.collect();
+		lines.sort();
+		lines.join("\n")
+	};
+
+	let mut reply = match user_data.macros.is_empty() {
+		true => "you have no macros".to_string(),
+		false => format!("**your macros**\n{}", list(&user_data.macros)),
+	};
+	if let Some(data) = guild_data.filter(|data| !data.macros.is_empty()) {
+		reply += &format!("\n**guild macros**\n{}", list(&data.macros));
+	}
+
+	reply
+}
+
+//	Names are letters, digits & underscores, and mustn't read as a roll or subcommand.
+fn check_macro_name(name: &str) -> Result<(), String> {
+	if name.is_empty() || name.chars().count() > 32 || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+		return Err("macro names are up to 32 letters, digits or underscores".to_string())
+	}
+	if SUBCOMMANDS.contains(&name) || DiceCommand::from_str(name).is_ok() {
+		return Err(format!("`{}` can't be a macro name, since it already means something", name))
+	}
+
+	Ok(())
+}
+
+//	Rolls, and remembers the roll in the channel's history if it worked. A fair roll
+//	uses the seed committed to by the roll before it, and commits to the next one.
+async fn roll_and_record(
+	channel_id: Id<ChannelMarker>,
+	user_id: Id<UserMarker>,
+	input: &str,
+	limits: &RollLimits,
+	scope: &Scope<'_>,
+	system: Option<GameSystem>,
+	fair: bool
+) -> BotResult<String> {
+	let _lock = ChannelData::lock(channel_id).await;
+	let mut channel = history::load(channel_id).await?;
+
+	//	A seed picked by the roller can't be committed to in advance. Without a seed
+	//	committed to yet, the roll is an ordinary one that commits to the next.
+	let fair = fair && !matches!(take_option(parse::split_comment(input).0, "--seed"), Ok((_, Some(_))));
+	let seed = channel.next_seed.filter(|_| fair);
+	let rolled = match roll_with(input, limits, scope, system, seed) {
+		Ok(rolled) => rolled,
+		Err(e) => return Ok(e)
+	};
+
+	channel.rolls += 1;
+	let mut record = RollRecord::new(channel.rolls, user_id, input, &rolled.text);
+	record.fingerprint = seed.map(|_| fair::fingerprint(&rolled.faces));
+	record.faces = fair::audited(rolled.faces);
+	record.seed = seed;
+	history::push(&mut channel.history, record);
+
+	let mut reply = rolled.text;
+	if fair {
+		let next = fair::new_seed();
+		channel.next_seed = Some(next);
+		let commitment = fair::commitment(channel_id, next);
+		reply += &match seed {
+			Some(_) => format!("\nroll #{} · next roll committed to `{}`", channel.rolls, commitment),
+			None => format!("\nfair rolls start here; the next roll is committed to `{}`", commitment)
+		};
+	}
+	channel.write_file().await?;
+
+	Ok(reply)
+}
+
+//	A roll's reply, along with every face it rolled by die size.
+struct Rolled {
+	text:String,
+	faces:BTreeMap<i32, Vec<i32>>
+}
+
+fn roll_reply(rest: &str, limits: &RollLimits, scope: &Scope, system: Option<GameSystem>) -> Result<String, String> {
+	roll_with(rest, limits, scope, system, None).map(|rolled| rolled.text)
+}
+
+//	`seed` is used when the roll doesn't name its own, and isn't shown in the reply.
+fn roll_with(
+	rest: &str,
+	limits: &RollLimits,
+	scope: &Scope,
+	system: Option<GameSystem>,
+	seed: Option<u64>
+) -> Result<Rolled, String> {
+	//	Options written in the comment are only part of it
+	let (rest, comment) = parse::split_comment(rest);
+	let (rest, shown_seed) = take_option(rest, "--seed")?;
+	let (rest, max_explosions) = take_option(&rest, "--max-explosions")?;
+	let (rest, skill) = take_option(&rest, "--skill")?;
+	let skill = skill.map(|skill| skill.min(i32::MAX as u64) as i32);
+	let (expr, sorted) = take_flag(&rest, "--sort");
+	let expr = format!("{} {}", expr, comment);
+
+	let (repeat, to_roll) = DiceCommand::parse_repeated(&expr, limits).map_err(|e| e.to_string())?;
+	let mut to_roll = to_roll.resolve(scope, limits).map_err(|e| e.to_string())?;
+	if let Some(n) = max_explosions {
+		if n > limits.max_explosions as u64 {
+			return Err(format!("dice can't explode more than {} times here", limits.max_explosions))
+		}
+		to_roll = to_roll.with_max_explosions(n as u32);
+	}
+
+	//	Repeats all draw from the one generator, so a seed covers the whole batch
+	let mut rng = rng(shown_seed.or(seed));
+	let rolls: Vec<RollResult> = (0..repeat)
+		.map(|_| to_roll.roll(&mut rng))
+		.collect::<Result<_, _>>()
+		.map_err(|e| e.to_string())?;
+
+	let mut faces = BTreeMap::new();
+	for roll in &rolls {
+		roll.rolled_faces(&mut faces);
+	}
+
+	let seed = shown_seed.map(|seed| format!(" (seed {seed})")).unwrap_or_default();
+	if let [roll] = &rolls[..] {
+		let note = outcome(system, roll, skill);
+		let reply = format!("you rolled: {roll}{seed}{note}");
+
+		//	Big pools are fine to roll, but not to list out die by die
+		let text = match reply.chars().count() > limits.max_output {
+			true => format!("you rolled: {}{} (too many dice to show){}", roll.total, seed, note),
+			false => reply
+		};
+		return Ok(Rolled{ text, faces })
+	}
+
+	let totals: Vec<i32> = rolls.iter().map(|roll| roll.total).collect();
+	let total = totals.iter().try_fold(0i32, |sum, t| sum.checked_add(*t))
+		.ok_or(RollError::Overflow.to_string())?;
+
+	let mut summary = format!("total: {}", total);
+	if sorted {
+		let mut order = totals.clone();
+		order.sort_unstable_by(|a, b| b.cmp(a));
+		let order: Vec<String> = order.iter().map(|t| t.to_string()).collect();
+		summary += &format!("\nsorted: {}", order.join(", "));
+	}
+
+	let lines: Vec<String> = rolls.iter().enumerate()
+		.map(|(i, roll)| format!("{}. {}{}", i + 1, roll, outcome(system, roll, skill)))
+		.collect();
+	let reply = format!("you rolled {}x {}{}:\n{}\n{}", repeat, to_roll, seed, lines.join("\n"), summary);
+
+	let text = match reply.chars().count() > limits.max_output {
+		true => {
+			let totals: Vec<String> = totals.iter().map(|t| t.to_string()).collect();
+			format!("you rolled {}x {}{}: {}\n{}", repeat, to_roll, seed, totals.join(", "), summary)
+		}
+		false => reply
+	};
+
+	Ok(Rolled{ text, faces })
+}
+
+//	" — <what the roll means>" under the game system in use, or nothing.
+fn outcome(system: Option<GameSystem>, roll: &RollResult, skill: Option<i32>) -> String {
+	system.and_then(|system| system.interpret(roll, skill))
+		.map(|note| format!(" — **{}**", note))
+		.unwrap_or_default()
+}
+
+//	Where every roll's randomness comes from. A seed replays the exact same dice, which
+//	is how disputed rolls get checked.
+pub fn rng(seed: Option<u64>) -> Box<dyn RngCore> {
+	match seed {
+		Some(seed) => Box::new(StdRng::seed_from_u64(seed)),
+		None => Box::new(rand::thread_rng()),
+	}
+}
+
+//	Cuts `text` down to `width` characters, with an ellipsis where it was cut.
+pub fn truncate(text: &str, width: usize) -> String {
+	match text.chars().count() > width {
+		true => format!("{}…", text.chars().take(width.saturating_sub(1)).collect::<String>()),
+		false => text.to_owned()
+	}
+}
+
+//	Pulls a flag like "--sort" out of the arguments, reporting whether it was there.
+fn take_flag(rest: &str, name: &str) -> (String, bool) {
+	let mut words: Vec<&str> = rest.split_whitespace().collect();
+	let found = words.iter().position(|&w| w == name).map(|i| words.remove(i)).is_some();
+
+	(words.join(" "), found)
+}
+
+//	Pulls a numeric option like "--seed N" out of the arguments, wherever it was written.
+pub fn take_option(rest: &str, name: &str) -> Result<(String, Option<u64>), String> {
+	let mut words: Vec<&str> = rest.split_whitespace().collect();
+	let Some(i) = words.iter().position(|&w| w == name) else {
+		return Ok((rest.to_owned(), None))
+	};
+
+	let value = words.get(i + 1)
+		.and_then(|w| w.parse::<u64>().ok())
+		.ok_or(format!("{} needs a whole number after it", name))?;
+	words.drain(i..=i + 1);
+
+	Ok((words.join(" "), Some(value)))
+}
+
+//	"stats <expr> [dc N]" - the odds of a roll, rather than a roll.
+async fn stats_reply(rest: &str, limits: &RollLimits, scope: &Scope<'_>) -> BotResult<String> {
+	let (expr, dc) = match rest.rsplit_once(char::is_whitespace) {
+		Some((expr, n)) => match (expr.trim_end().strip_suffix("dc"), n.parse::<i32>()) {
+			(Some(expr), Ok(n)) => (expr.trim(), Some(n)),
+			_ => (rest, None)
+		},
+		None => (rest, None)
+	};
+
+	let command = match DiceCommand::parse_with(expr, limits) {
+		Ok(command) => command,
+		Err(e) => return Ok(format!("{}", e)),
+	};
+	let command = match command.resolve(scope, limits) {
+		Ok(command) => command,
+		Err(e) => return Ok(format!("{}", e)),
+	};
+	//	Big pools can take a while to work out, so they're kept off the event loop
+	let (command, dist) = tokio::task::spawn_blocking(move || {
+		let dist = command.distribution();
+		(command, dist)
+	}).await?;
+	let dist = match dist {
+		Ok(dist) => dist,
+		Err(e) => return Ok(format!("{}", e)),
+	};
+
+	let mut reply = format!("**{}**\n{}", command, dist);
+	if let Some(dc) = dc {
+		reply += &format!("\nP(≥ {}): {:.2}%", dc, dist.at_least(dc) * 100.0);
+	}
+
+	Ok(reply)
+}
+
+//		Implementation
+//  Structs
+//	Stored as its canonical text, so saved rolls stay readable & go back through the
+//	parser when they're loaded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DiceCommand {
+	expr:Expr,
+
+	//	How many extra dice one die can explode into before it has to stop
+	max_explosions:u32,
+
+	//	What the roll is for, written after a '#': "1d20+5 # longsword"
+	comment:Option<String>
+}
+
+const DEFAULT_MAX_EXPLOSIONS: u32 = 100;
+
+//	Discord won't send a message longer than this, in characters
+pub const MAX_MESSAGE: usize = 2000;
+
+//	Bounds on how much work one roll can ask for, set per guild. Everything but the
+//	output length is checked while parsing.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RollLimits {
+	//	Dice across the whole expression, not counting explosions
+	pub max_dice:u32,
+	pub max_sides:u32,
+	//	Numbers & dice terms in the expression
+	pub max_terms:u32,
+	pub max_explosions:u32,
+	//	Times one message can repeat a roll, as in "6x 4d6kh3"
+	pub max_repeats:u32,
+	//	Characters in a roll's breakdown before only the total is shown
+	pub max_output:usize
+}
+
+impl Default for RollLimits {
+	fn default() -> Self {
+		RollLimits{
+			max_dice: 1000,
+			max_sides: 10_000,
+			max_terms: 100,
+			max_explosions: DEFAULT_MAX_EXPLOSIONS,
+			max_repeats: 20,
+			max_output: 1900
+		}
+	}
+}
+
+impl RollLimits {
+	//	Each limit can be raised only so far, so one roll can't tie up the bot or ask for
+	//	a message Discord won't send.
+	pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+		let value: u32 = value.parse()
+			.map_err(|_| format!("{} needs a whole number", name))?;
+		let within = |most: u32| match value {
+			1.. if value <= most => Ok(value),
+			_ => Err(format!("{} has to be 1 to {}", name, most))
+		};
+
+		match name {
+			"max_dice" => self.max_dice = within(10_000)?,
+			"max_sides" => self.max_sides = within(1_000_000)?,
+			"max_terms" => self.max_terms = within(1000)?,
+			"max_explosions" => self.max_explosions = within(1000)?,
+			"max_repeats" => self.max_repeats = within(100)?,
+			"max_output" => self.max_output = within(MAX_MESSAGE as u32)? as usize,
+			_ => return Err(format!(
+				"unknown limit `{}`; try max_dice, max_sides, max_terms, max_explosions, max_repeats or max_output", name
+			))
+		}
+
+		Ok(())
+	}
+}
+
+impl fmt::Display for RollLimits {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f,
+			"max_dice: {} · max_sides: {} · max_terms: {} · max_explosions: {} · max_repeats: {} · max_output: {}",
+			self.max_dice, self.max_sides, self.max_terms, self.max_explosions, self.max_repeats, self.max_output
+		)
+	}
+}
+
+//	Expression tree produced by the parser. Precedence is encoded in the shape of the
+//	tree, so parentheses don't need a node of their own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+	Num(i32),
+	Dice(Dice),
+	Neg(Box<Expr>),
+	Op(ArithOp, Box<Expr>, Box<Expr>),
+	//	References to a saved macro or a character variable, filled in by `resolve`
+	//	before rolling
+	Macro(String),
+	Var(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Dice {
+	count:i32,
+	kind:DieKind,
+
+	args:Vec<DiceArg>,
+	//	What the dice stand for, e.g. the damage type in "2d6[slashing]"
+	label:Option<String>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DieKind {
+	//	The usual 1..=n die; d% is read as a d100
+	Numeric(i32),
+	//	Fate/Fudge dice, -1/0/+1
+	Fate,
+	//	Faces listed out by hand, e.g. d{1,1,2,3,5,8} or d{hit,miss,crit}
+	Custom(Vec<Side>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Side {
+	Num(i32),
+	//	Symbols are tallied in the result, but are worth nothing in arithmetic
+	Symbol(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiceArg {
+	Advantage(bool),
+	Extra(i32, i32),
+	Reroll(i32, i32),
+
+	//	Explosions that add onto the exploding die, and that add extra dice worth one
+	//	less than they rolled
+	Compound(i32, i32),
+	Penetrate(i32, i32),
+
+	//	Keep/drop the highest (true) or lowest (false) n dice of the pool
+	Keep(bool, i32),
+	Drop(bool, i32),
+
+	//	Faces that count as a success/failure; either turns the pool into a count
+	Success(i32, i32),
+	Failure(i32, i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ArithOp {
+	Add,
+	Sub,
+	Mul,
+	Div,
+	DivCeil,
+}
+
+impl DiceCommand {
+	pub fn with_max_explosions(mut self, max_explosions: u32) -> Self {
+		self.max_explosions = max_explosions;
+		self
+	}
+}
+
+impl TryFrom<String> for DiceCommand {
+	type Error = parse::ParseRollError;
+
+	fn try_from(s: String) -> Result<Self, Self::Error> {
+		DiceCommand::parse_unchecked(&s)
+	}
+}
+
+impl From<DiceCommand> for String {
+	fn from(command: DiceCommand) -> Self {
+		command.to_string()
+	}
+}
+
+impl DieKind {
+	//	Number of faces on the die.
+	fn faces(&self) -> usize {
+		match self {
+			DieKind::Numeric(sides) => *sides as usize,
+			DieKind::Fate => 3,
+			DieKind::Custom(sides) => sides.len(),
+		}
+	}
+
+	//	Value of the face at `index`, counting from zero.
+	fn value(&self, index: usize) -> i32 {
+		match self {
+			DieKind::Numeric(_) => index as i32 + 1,
+			DieKind::Fate => index as i32 - 1,
+			DieKind::Custom(sides) => match sides.get(index) {
+				Some(Side::Num(n)) => *n,
+				_ => 0
+			}
+		}
+	}
+
+	fn min(&self) -> i32 {
+		(0..self.faces()).map(|i| self.value(i)).min().unwrap_or(0)
+	}
+
+	fn max(&self) -> i32 {
+		(0..self.faces()).map(|i| self.value(i)).max().unwrap_or(0)
+	}
+}
+
+impl fmt::Display for DieKind {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			DieKind::Numeric(sides) => write!(f, "{}", sides),
+			DieKind::Fate => write!(f, "F"),
+			DieKind::Custom(sides) => {
+				let sides: Vec<String> = sides.iter().map(|side| side.to_string()).collect();
+				write!(f, "{{{}}}", sides.join(","))
+			}
+		}
+	}
+}
+
+impl fmt::Display for Side {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Side::Num(n) => write!(f, "{}", n),
+			Side::Symbol(s) => write!(f, "{}", s),
+		}
+	}
+}
+
+//	The canonical form of a roll: what was evaluated once modifiers are merged, written
+//	so it parses back to the same thing.
+impl fmt::Display for DiceCommand {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.expr)?;
+		match &self.comment {
+			Some(comment) => write!(f, " # {}", comment),
+			None => Ok(())
+		}
+	}
+}
+
+impl fmt::Display for Expr {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Expr::Num(n) => write!(f, "{}", n),
+			Expr::Dice(dice) => write!(f, "{}", dice),
+			Expr::Macro(name) => write!(f, "${}", name),
+			Expr::Var(name) => write!(f, "@{}", name),
+			Expr::Neg(inner) => match **inner {
+				Expr::Op(..) => write!(f, "-({})", inner),
+				_ => write!(f, "-{}", inner),
+			},
+			Expr::Op(op, lhs, rhs) => {
+				//	Same bracketing as a rolled result, but with a plain '*'
+				let wrap = |child: &Expr, right: bool| match child {
+					Expr::Op(inner, ..) => inner.precedence() < op.precedence()
+						|| (right && inner.precedence() == op.precedence()),
+					_ => false
+				};
+				let symbol = match op {
+					ArithOp::Add => "+",
+					ArithOp::Sub => "-",
+					ArithOp::Mul => "*",
+					ArithOp::Div => "/",
+					ArithOp::DivCeil => "/^",
+				};
+
+				match wrap(lhs, false) {
+					true => write!(f, "({})", lhs)?,
+					false => write!(f, "{}", lhs)?,
+				}
+				write!(f, " {} ", symbol)?;
+				match wrap(rhs, true) {
+					true => write!(f, "({})", rhs),
+					false => write!(f, "{}", rhs),
+				}
+			}
+		}
+	}
+}
+
+//	Modifiers need the faces of the die: a comparison like ">=5" only means (5, max)
+//	on a die whose highest face is max.
+impl fmt::Display for Dice {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}d{}", self.count, self.kind)?;
+
+		let (min, max) = (self.kind.min(), self.kind.max());
+		let compare = |l: i32, r: i32| match (l, r) {
+			(l, r) if l == r => format!("={}", l),
+			(l, r) if l == min && r != max => format!("<={}", r),
+			//	Anything else came from a '>', which always runs up to the top face
+			(l, _) => format!(">={}", l),
+		};
+		let range = |l: i32, r: i32| match l == r {
+			true => format!("{}", l),
+			false => format!("{}..{}", l, r),
+		};
+		let side = |highest: bool| if highest { "h" } else { "l" };
+
+		for arg in &self.args {
+			match *arg {
+				DiceArg::Advantage(true) => write!(f, "a")?,
+				DiceArg::Advantage(false) => write!(f, "d")?,
+				//	Ranges can't hold negative numbers, but those only come from comparisons
+				DiceArg::Extra(l, r) if l < 0 || r < 0 => write!(f, "!{}", compare(l, r))?,
+				DiceArg::Extra(l, r) => write!(f, "x{}", range(l, r))?,
+				DiceArg::Reroll(l, r) => write!(f, "r{}", range(l, r))?,
+				DiceArg::Compound(l, r) => write!(f, "!!{}", compare(l, r))?,
+				DiceArg::Penetrate(l, r) => write!(f, "!p{}", compare(l, r))?,
+				DiceArg::Keep(highest, n) => write!(f, "k{}{}", side(highest), n)?,
+				DiceArg::Drop(highest, n) => write!(f, "d{}{}", side(highest), n)?,
+				DiceArg::Success(l, r) => write!(f, "{}", compare(l, r))?,
+				DiceArg::Failure(l, r) => write!(f, "f{}", compare(l, r))?,
+			}
+		}
+
+		match &self.label {
+			Some(label) => write!(f, "[{}]", label),
+			None => Ok(())
+		}
+	}
+}
@@ -0,0 +1,1132 @@
+//		Imports
+use std::{
+	fmt,
+	str::FromStr,
+};
+
+use super::{
+	DiceCommand, Expr, Dice, DieKind, Side, DiceArg, ArithOp,
+	RollLimits
+};
+
+//		Data
+//	Where something is in the input, counted in characters from the start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+	pub start:usize,
+	pub end:usize
+}
+
+//	Keeps a copy of the input so the error can point at where it went wrong.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseRollError {
+	pub kind:ParseErrorKind,
+	pub span:Span,
+	input:String
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseErrorKind {
+	Unexpected(String, Expected),
+	UnexpectedEnd(Expected),
+	Unclosed(char),
+	NumberTooLarge,
+	ZeroSides,
+	NoFaces,
+	NoLabel,
+
+	//	Over one of the guild's RollLimits, which is carried along to show
+	TooManyDice(u32),
+	TooManySides(u32),
+	TooManyTerms(u32),
+	BadRepeat(u32),
+}
+
+//	What the parser was looking for when it hit something else.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expected {
+	Expression,
+	Operator,
+	Modifier,
+	Sides,
+	Number,
+	Close,
+	Name,
+}
+
+impl fmt::Display for Expected {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Expression => write!(f, "a number, dice or `(`"),
+			Self::Operator => write!(f, "an operator (+, -, *, /)"),
+			Self::Modifier => write!(f, "a modifier (r, x, !, a, d, kh, kl, dh, dl, >=, f), a [label] or an operator"),
+			Self::Sides => write!(f, "the number of sides (e.g. d6, d%, dF or d{{1,2,3}})"),
+			Self::Number => write!(f, "a number"),
+			Self::Close => write!(f, "a closing `)`"),
+			Self::Name => write!(f, "a name, e.g. $attack or @str"),
+		}
+	}
+}
+
+//	e.g. "unexpected `q` at column 4: expected a modifier ...", followed by the input
+//	with a caret under the problem.
+impl fmt::Display for ParseRollError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let column = self.span.start + 1;
+		match &self.kind {
+			ParseErrorKind::Unexpected(found, expected) =>
+				write!(f, "unexpected `{}` at column {}: expected {}", found, column, expected)?,
+			ParseErrorKind::UnexpectedEnd(expected) =>
+				write!(f, "unexpected end of roll at column {}: expected {}", column, expected)?,
+			ParseErrorKind::Unclosed(c) =>
+				write!(f, "unclosed `{}` at column {}", c, column)?,
+			ParseErrorKind::NumberTooLarge =>
+				write!(f, "number too large at column {}", column)?,
+			ParseErrorKind::ZeroSides =>
+				write!(f, "dice need at least one side, at column {}", column)?,
+			ParseErrorKind::NoFaces =>
+				write!(f, "custom dice need at least one face, e.g. d{{1,2,3}}, at column {}", column)?,
+			ParseErrorKind::NoLabel =>
+				write!(f, "labels need some text, e.g. 2d6[fire], at column {}", column)?,
+			ParseErrorKind::TooManyDice(max) =>
+				write!(f, "too many dice at column {}: rolls here can use at most {}", column, max)?,
+			ParseErrorKind::TooManySides(max) =>
+				write!(f, "too many sides at column {}: dice here can have at most {}", column, max)?,
+			ParseErrorKind::TooManyTerms(max) =>
+				write!(f, "roll too long at column {}: rolls here can have at most {} terms", column, max)?,
+			ParseErrorKind::BadRepeat(max) =>
+				write!(f, "can't repeat a roll that many times at column {}: it has to be 1 to {}", column, max)?,
+		}
+
+		let width = self.span.end.saturating_sub(self.span.start).max(1);
+		write!(f, "\n```\n{}\n{}{}\n```", self.input, " ".repeat(self.span.start), "^".repeat(width))
+	}
+}
+
+impl ParseRollError {
+	fn new(input: &str, kind: ParseErrorKind, span: Span) -> Self {
+		ParseRollError{ kind, span, input: input.to_owned() }
+	}
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+	Num(i32),
+	Sym(char),
+	//	The raw contents of a {...} face list, or of a [...] label
+	Faces(String),
+	Label(String),
+	//	Everything after a '#', which always comes last
+	Comment(String),
+	//	A saved macro, "$name", or a character variable, "@name"
+	Macro(String),
+	Var(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Token {
+	kind:TokenKind,
+	span:Span
+}
+
+//	Recursive descent parser over the tokens of the command. A repeat count like
+//	"6x" is only looked for at the very start, by parse_repeated.
+//		command := expr ('#' comment)?
+//		expr  := term (('+' | '-') term)*
+//		term  := unary (('*' | '/' | '/^') unary)*
+//		unary := ('-' | '+') unary | atom
+//		atom  := '(' expr ')' | number | number? 'd' kind arg* label? | ('$' | '@') name
+//		kind  := number | '%' | 'F' | '{' side (',' side)* '}'
+//		arg   := 'a' | 'd' | ('!' | '!!' | '!p') compare? | ('x' | 'r') range
+//		       | ('k' | 'kh' | 'kl' | 'dh' | 'dl') number?
+//		       | compare | 'f' (compare | number)
+//		compare := ('>' | '>=' | '<' | '<=' | '=') '-'? number
+//		label := '[' text ']'
+struct Parser {
+	input:String,
+	tokens:Vec<Token>,
+	pos:usize,
+
+	//	Whether the last atom was a dice term, so leftovers can be blamed on a modifier
+	after_dice:bool,
+
+	limits:RollLimits,
+	dice:u32,
+	terms:u32
+}
+
+//		Functions
+impl FromStr for DiceCommand {
+	type Err = ParseRollError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		DiceCommand::parse_with(s, &RollLimits::default())
+	}
+}
+
+impl DiceCommand {
+	//	Parses a roll, refusing anything bigger than `limits` allows.
+	pub fn parse_with(s: &str, limits: &RollLimits) -> Result<Self, ParseRollError> {
+		let mut parser = Parser::new(s, limits.clone())?;
+		parser.command()
+	}
+
+	//	Saved rolls are read back without the limits, since the guild's may have changed
+	//	since they were saved. They're checked again whenever they're resolved.
+	pub(super) fn parse_unchecked(s: &str) -> Result<Self, ParseRollError> {
+		let unlimited = RollLimits{
+			max_dice: u32::MAX,
+			max_sides: u32::MAX,
+			max_terms: u32::MAX,
+			..RollLimits::default()
+		};
+		DiceCommand::parse_with(s, &unlimited)
+	}
+
+	//	A roll that may start with a repeat count, "6x 4d6kh3" or "3# 1d20+5". Rolls
+	//	without one are done once.
+	pub fn parse_repeated(s: &str, limits: &RollLimits) -> Result<(u32, Self), ParseRollError> {
+		let mut parser = Parser::new(s, limits.clone())?;
+
+		let repeat = match parser.tokens.get(..2).map(|t| (&t[0].kind, &t[1].kind)) {
+			Some((&TokenKind::Num(n), TokenKind::Sym('x' | '#'))) => {
+				let span = Span{ start: parser.tokens[0].span.start, end: parser.tokens[1].span.end };
+				if n < 1 || n as u32 > limits.max_repeats {
+					return Err(parser.error(ParseErrorKind::BadRepeat(limits.max_repeats), span))
+				}
+
+				parser.pos = 2;
+				n as u32
+			}
+			_ => 1
+		};
+
+		Ok((repeat, parser.command()?))
+	}
+}
+
+//	Splits the input into numbers, face lists, labels, a trailing comment & single
+//	characters. Whitespace only separates tokens.
+fn tokenize(input: &str) -> Result<Vec<Token>, ParseRollError> {
+	let chars: Vec<char> = input.chars().collect();
+	let mut tokens: Vec<Token> = vec![];
+
+	let mut pos: usize = 0;
+	while let Some(&c) = chars.get(pos) {
+		let start = pos;
+		let kind = match c {
+			c if c.is_whitespace() => { pos += 1; continue }
+			'0'..='9' => {
+				while chars.get(pos).is_some_and(|c| c.is_ascii_digit()) { pos += 1; }
+
+				let digits: String = chars[start..pos].iter().collect();
+				match digits.parse::<i32>() {
+					Ok(n) => TokenKind::Num(n),
+					Err(_) => return Err(ParseRollError::new(
+						input, ParseErrorKind::NumberTooLarge, Span{ start, end: pos }
+					))
+				}
+			}
+			//	Faces are read raw, so symbols can have spaces in them
+			'{' => match chars[start..].iter().position(|c| *c == '}') {
+				Some(len) => {
+					pos = start + len + 1;
+					TokenKind::Faces(chars[start + 1..start + len].iter().collect())
+				}
+				None => return Err(ParseRollError::new(
+					input, ParseErrorKind::Unclosed('{'), Span{ start, end: start + 1 }
+				))
+			}
+			'[' => match chars[start..].iter().position(|c| *c == ']') {
+				Some(len) => {
+					pos = start + len + 1;
+					TokenKind::Label(chars[start + 1..start + len].iter().collect())
+				}
+				None => return Err(ParseRollError::new(
+					input, ParseErrorKind::Unclosed('['), Span{ start, end: start + 1 }
+				))
+			}
+			//	Straight after a leading number, as in "3# 1d20", it's a repeat instead
+			'#' if !matches!(&tokens[..], [Token{ kind: TokenKind::Num(_), span }] if span.end == start) => {
+				pos = chars.len();
+				TokenKind::Comment(chars[start + 1..].iter().collect::<String>().trim().to_owned())
+			}
+			'$' | '@' => {
+				pos += 1;
+				while chars.get(pos).is_some_and(|c| c.is_alphanumeric() || *c == '_') { pos += 1; }
+				if pos == start + 1 {
+					return Err(ParseRollError::new(
+						input, ParseErrorKind::Unexpected(c.to_string(), Expected::Name), Span{ start, end: pos }
+					))
+				}
+
+				let name: String = chars[start + 1..pos].iter().collect();
+				match c {
+					'$' => TokenKind::Macro(name),
+					_ => TokenKind::Var(name)
+				}
+			}
+			c => { pos += 1; TokenKind::Sym(c) }
+		};
+
+		tokens.push(Token{ kind, span: Span{ start, end: pos } });
+	}
+
+	Ok(tokens)
+}
+
+impl Parser {
+	fn new(s: &str, limits: RollLimits) -> Result<Self, ParseRollError> {
+		Ok(Parser{
+			input: s.to_owned(),
+			tokens: tokenize(s)?,
+			pos: 0,
+			after_dice: false,
+			limits,
+			dice: 0,
+			terms: 0
+		})
+	}
+
+	fn peek(&self) -> Option<&TokenKind> {
+		self.tokens.get(self.pos).map(|token| &token.kind)
+	}
+
+	fn sym(&self) -> Option<char> {
+		match self.peek() {
+			Some(TokenKind::Sym(c)) => Some(*c),
+			_ => None
+		}
+	}
+
+	fn bump(&mut self) {
+		self.pos += 1;
+	}
+
+	//	The span of the next token, or just past the end of the input.
+	fn span(&self) -> Span {
+		match self.tokens.get(self.pos) {
+			Some(token) => token.span,
+			None => {
+				let end = self.input.chars().count();
+				Span{ start: end, end: end + 1 }
+			}
+		}
+	}
+
+	fn error(&self, kind: ParseErrorKind, span: Span) -> ParseRollError {
+		ParseRollError::new(&self.input, kind, span)
+	}
+
+	fn unexpected(&self, expected: Expected) -> ParseRollError {
+		let span = self.span();
+		match self.tokens.get(self.pos) {
+			Some(_) => {
+				let found: String = self.input.chars().skip(span.start).take(span.end - span.start).collect();
+				self.error(ParseErrorKind::Unexpected(found, expected), span)
+			}
+			None => self.error(ParseErrorKind::UnexpectedEnd(expected), span)
+		}
+	}
+
+	fn command(&mut self) -> Result<DiceCommand, ParseRollError> {
+		let expr = self.expr()?;
+
+		let comment = match self.peek() {
+			Some(TokenKind::Comment(comment)) => {
+				let comment = Some(comment.clone()).filter(|comment| !comment.is_empty());
+				self.bump();
+				comment
+			}
+			_ => None
+		};
+
+		//	Anything left over wasn't consumed by the grammar
+		if self.peek().is_some() {
+			let expected = if self.after_dice { Expected::Modifier } else { Expected::Operator };
+			return Err(self.unexpected(expected))
+		}
+
+		Ok(DiceCommand{ expr, max_explosions: self.limits.max_explosions, comment })
+	}
+
+	fn expr(&mut self) -> Result<Expr, ParseRollError> {
+		let mut lhs = self.term()?;
+		loop {
+			let op = match self.sym() {
+				Some('+') => ArithOp::Add,
+				Some('-') => ArithOp::Sub,
+				_ => break
+			};
+			self.bump();
+
+			let rhs = self.term()?;
+			lhs = Expr::Op(op, Box::new(lhs), Box::new(rhs));
+		}
+
+		Ok(lhs)
+	}
+
+	fn term(&mut self) -> Result<Expr, ParseRollError> {
+		let mut lhs = self.unary()?;
+		loop {
+			let op = match self.sym() {
+				Some('*') => ArithOp::Mul,
+				Some('/') => ArithOp::Div,
+				_ => break
+			};
+			self.bump();
+
+			//	"/^" rounds the quotient up instead of down
+			let op = match (op, self.sym()) {
+				(ArithOp::Div, Some('^')) => { self.bump(); ArithOp::DivCeil },
+				(op, _) => op
+			};
+
+			let rhs = self.unary()?;
+			lhs = Expr::Op(op, Box::new(lhs), Box::new(rhs));
+		}
+
+		Ok(lhs)
+	}
+
+	fn unary(&mut self) -> Result<Expr, ParseRollError> {
+		match self.sym() {
+			Some('-') => {
+				self.bump();
+				Ok(Expr::Neg(Box::new(self.unary()?)))
+			}
+			Some('+') => {
+				self.bump();
+				self.unary()
+			}
+			_ => self.atom()
+		}
+	}
+
+	fn atom(&mut self) -> Result<Expr, ParseRollError> {
+		self.after_dice = false;
+		if !matches!(self.peek(), Some(TokenKind::Sym('('))) {
+			self.terms += 1;
+			if self.terms > self.limits.max_terms {
+				return Err(self.error(ParseErrorKind::TooManyTerms(self.limits.max_terms), self.span()))
+			}
+		}
+
+		let span = self.span();
+		match self.peek() {
+			Some(TokenKind::Sym('(')) => {
+				let open = self.span();
+				self.bump();
+
+				let inner = self.expr()?;
+				self.after_dice = false;
+				match self.sym() {
+					Some(')') => { self.bump(); Ok(inner) }
+					_ if self.peek().is_none() => Err(self.error(ParseErrorKind::Unclosed('('), open)),
+					_ => Err(self.unexpected(Expected::Close))
+				}
+			}
+			Some(&TokenKind::Num(n)) => {
+				self.bump();
+				match self.sym() {
+					Some('d') => self.dice(n, span),
+					_ => Ok(Expr::Num(n))
+				}
+			}
+			Some(TokenKind::Sym('d')) => self.dice(1, span),
+			Some(TokenKind::Macro(name)) => {
+				let name = name.clone();
+				self.bump();
+				Ok(Expr::Macro(name))
+			}
+			Some(TokenKind::Var(name)) => {
+				let name = name.clone();
+				self.bump();
+				Ok(Expr::Var(name))
+			}
+			_ => Err(self.unexpected(Expected::Expression))
+		}
+	}
+
+	fn number(&mut self) -> Option<i32> {
+		match self.peek() {
+			Some(&TokenKind::Num(n)) => { self.bump(); Some(n) }
+			_ => None
+		}
+	}
+
+	//	Called with the cursor on the 'd' separating count & kind; `span` is where the
+	//	term started.
+	fn dice(&mut self, count: i32, span: Span) -> Result<Expr, ParseRollError> {
+		self.dice = self.dice.saturating_add(count as u32);
+		if self.dice > self.limits.max_dice {
+			return Err(self.error(ParseErrorKind::TooManyDice(self.limits.max_dice), span))
+		}
+
+		self.bump();
+		let kind = self.kind()?;
+		let (min, max) = (kind.min(), kind.max());
+
+		let mut args: Vec<DiceArg> = vec![];
+		loop {
+			let arg = match self.sym() {
+				Some('k') => {
+					self.bump();
+					let highest = self.direction().unwrap_or(true);
+					DiceArg::Keep(highest, self.number().unwrap_or(1))
+				}
+				Some('d') => {
+					//	A bare 'd' is still disadvantage; "dh"/"dl" drop dice
+					self.bump();
+					match self.direction() {
+						Some(highest) => DiceArg::Drop(highest, self.number().unwrap_or(1)),
+						None => DiceArg::Advantage(false)
+					}
+				}
+				Some('a') => { self.bump(); DiceArg::Advantage(true) }
+				Some('x') => { self.bump(); let (l, r) = self.range(); DiceArg::Extra(l, r) }
+				Some('r') => { self.bump(); let (l, r) = self.range(); DiceArg::Reroll(l, r) }
+				Some('!') => {
+					//	Explodes on the highest face unless told otherwise
+					self.bump();
+					let kind = match self.sym() {
+						Some('!') => { self.bump(); DiceArg::Compound }
+						Some('p') => { self.bump(); DiceArg::Penetrate }
+						_ => DiceArg::Extra
+					};
+					let (l, r) = self.compare(min, max)?.unwrap_or((max, max));
+					kind(l, r)
+				}
+				Some('f') => {
+					self.bump();
+					let (l, r) = match self.compare(min, max)? {
+						Some(range) => range,
+						None => match self.number() {
+							Some(n) => (n, n),
+							None => return Err(self.unexpected(Expected::Number))
+						}
+					};
+					DiceArg::Failure(l, r)
+				}
+				Some(_) => match self.compare(min, max)? {
+					Some((l, r)) => DiceArg::Success(l, r),
+					None => break
+				}
+				None => break
+			};
+			args.push(arg);
+		}
+
+		let label = match self.peek() {
+			Some(TokenKind::Label(label)) if label.trim().is_empty() =>
+				return Err(self.error(ParseErrorKind::NoLabel, self.span())),
+			Some(TokenKind::Label(label)) => {
+				let label = label.trim().to_owned();
+				self.bump();
+				Some(label)
+			}
+			_ => None
+		};
+
+		self.after_dice = true;
+		Ok(Expr::Dice(Dice{
+			count,
+			kind,
+			args: merge_args(args),
+			label
+		}))
+	}
+
+	fn kind(&mut self) -> Result<DieKind, ParseRollError> {
+		let span = self.span();
+		let kind = match self.peek() {
+			Some(TokenKind::Sym('%')) if self.limits.max_sides < 100 =>
+				return Err(self.error(ParseErrorKind::TooManySides(self.limits.max_sides), span)),
+			Some(TokenKind::Sym('%')) => DieKind::Numeric(100),
+			Some(TokenKind::Sym('F')) => DieKind::Fate,
+			Some(TokenKind::Faces(list)) => {
+				let sides: Vec<Side> = list.split(',')
+					.map(|side| side.trim())
+					.filter(|side| !side.is_empty())
+					.map(|side| match side.parse::<i32>() {
+						Ok(n) => Side::Num(n),
+						Err(_) => Side::Symbol(side.to_owned())
+					})
+					.collect();
+				if sides.is_empty() { return Err(self.error(ParseErrorKind::NoFaces, span)) }
+				if sides.len() > self.limits.max_sides as usize {
+					return Err(self.error(ParseErrorKind::TooManySides(self.limits.max_sides), span))
+				}
+
+				DieKind::Custom(sides)
+			}
+			Some(TokenKind::Num(0)) => return Err(self.error(ParseErrorKind::ZeroSides, span)),
+			Some(&TokenKind::Num(sides)) if sides as u32 > self.limits.max_sides =>
+				return Err(self.error(ParseErrorKind::TooManySides(self.limits.max_sides), span)),
+			Some(&TokenKind::Num(sides)) => DieKind::Numeric(sides),
+			_ => return Err(self.unexpected(Expected::Sides))
+		};
+		self.bump();
+
+		Ok(kind)
+	}
+
+	//	The 'h'/'l' suffix of a keep or drop, if there is one.
+	fn direction(&mut self) -> Option<bool> {
+		let highest = match self.sym() {
+			Some('h') => true,
+			Some('l') => false,
+			_ => return None
+		};
+		self.bump();
+
+		Some(highest)
+	}
+
+	//	A comparison against the faces of the die, turned into the range of faces it
+	//	matches.
+	fn compare(&mut self, min: i32, max: i32) -> Result<Option<(i32, i32)>, ParseRollError> {
+		let cmp = match self.sym() {
+			Some(c @ ('<'|'>'|'=')) => c,
+			_ => return Ok(None)
+		};
+		self.bump();
+
+		let inclusive = cmp == '=' || self.sym() == Some('=');
+		if cmp != '=' && inclusive { self.bump(); }
+
+		//	Nothing else can follow a comparison, so a '-' here is part of the number
+		let negative = self.sym() == Some('-');
+		if negative { self.bump(); }
+
+		let n = match self.number() {
+			Some(n) if negative => -n,
+			Some(n) => n,
+			None => return Err(self.unexpected(Expected::Number))
+		};
+
+		Ok(Some(match (cmp, inclusive) {
+			('>', true) => (n, max),
+			('>', false) => (n.saturating_add(1), max),
+			('<', true) => (min, n),
+			('<', false) => (min, n.saturating_sub(1)),
+			_ => (n, n)
+		}))
+	}
+
+	//	Ranges are written "l..r", with either end optional; a lone number is a
+	//	single value.
+	fn range(&mut self) -> (i32, i32) {
+		let left = self.number();
+		if self.sym() != Some('.') {
+			return match left {
+				Some(n) => (n, n),
+				None => (0, 100)
+			}
+		}
+
+		while self.sym() == Some('.') { self.bump(); }
+		let right = self.number();
+
+		(left.unwrap_or(0), right.unwrap_or(100))
+	}
+}
+
+//	Splits a roll into what comes before its "# comment", and the comment. As when
+//	tokenizing, a '#' straight after a leading number is a repeat, and one inside a
+//	[label] is part of the label.
+pub fn split_comment(input: &str) -> (&str, &str) {
+	let leading = input.trim_start();
+	let from = leading.find(|c: char| !c.is_ascii_digit())
+		.filter(|&i| i > 0 && leading[i..].starts_with('#'))
+		.map_or(0, |i| input.len() - leading.len() + i + 1);
+
+	let mut label = false;
+	for (i, c) in input.char_indices().skip_while(|&(i, _)| i < from) {
+		match c {
+			'[' => label = true,
+			']' => label = false,
+			'#' if !label => return input.split_at(i),
+			_ => {}
+		}
+	}
+
+	(input, "")
+}
+
+//	The expressions inside each "[[...]]" of an ordinary message, in order. Labels
+//	inside are skipped over, so "[[2d6[fire]]]" is one roll.
+pub fn inline_rolls(text: &str) -> Vec<&str> {
+	let mut out: Vec<&str> = vec![];
+
+	let mut rest = text;
+	while let Some(start) = rest.find("[[") {
+		let inner = &rest[start + 2..];
+		let mut depth: u32 = 0;
+		let mut close: Option<usize> = None;
+		for (i, c) in inner.char_indices() {
+			match c {
+				'[' => depth += 1,
+				']' if depth > 0 => depth -= 1,
+				']' if inner[i..].starts_with("]]") => { close = Some(i); break }
+				_ => {}
+			}
+		}
+		let Some(len) = close else { break };
+
+		let expr = inner[..len].trim();
+		if !expr.is_empty() { out.push(expr); }
+
+		rest = &inner[len + 2..];
+	}
+
+	out
+}
+
+fn merge_args(args: Vec<DiceArg>) -> Vec<DiceArg> {
+	let mut out: Vec<DiceArg> = vec![];
+
+	//	Several ranges narrow down to their overlap
+	let narrow = |range: Option<(i32, i32)>, left: i32, right: i32| match range {
+		Some((l, r)) => Some((l.max(left), r.min(right))),
+		None => Some((left, right))
+	};
+	let mut extra: Option<(i32, i32)> = None;
+	let mut reroll: Option<(i32, i32)> = None;
+
+	let mut adv: i32 = 0;
+	let mut subset: Option<DiceArg> = None;
+	let mut success: Option<DiceArg> = None;
+	let mut failure: Option<DiceArg> = None;
+	for arg in args {
+		match arg {
+			DiceArg::Advantage(polarity) => { adv += if polarity {1} else {-1}; },
+			DiceArg::Extra(left, right) => {
+				if left == right { out.push(arg); continue; }
+				extra = narrow(extra, left, right);
+			},
+			DiceArg::Reroll(left, right) => {
+				if left == right { out.push(arg); continue; }
+				reroll = narrow(reroll, left, right);
+			},
+			DiceArg::Compound(..) | DiceArg::Penetrate(..) => { out.push(arg); }
+			//	Only one keep/drop makes sense per pool, the last one written wins
+			DiceArg::Keep(..) | DiceArg::Drop(..) => { subset = Some(arg); }
+			DiceArg::Success(..) => { success = Some(arg); }
+			DiceArg::Failure(..) => { failure = Some(arg); }
+		}
+	}
+
+	if adv != 0 { out.push(DiceArg::Advantage(adv > 0)); }
+	if let Some((left, right)) = extra { out.push(DiceArg::Extra(left, right)); }
+	if let Some((left, right)) = reroll { out.push(DiceArg::Reroll(left, right)); }
+	if let Some(arg) = subset { out.push(arg); }
+	if let Some(arg) = success { out.push(arg); }
+	if let Some(arg) = failure { out.push(arg); }
+
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn dice(command: &DiceCommand) -> Vec<&Dice> {
+		fn collect<'a>(expr: &'a Expr, out: &mut Vec<&'a Dice>) {
+			match expr {
+				Expr::Num(_) | Expr::Macro(_) | Expr::Var(_) => {}
+				Expr::Dice(die) => out.push(die),
+				Expr::Neg(inner) => collect(inner, out),
+				Expr::Op(_, lhs, rhs) => { collect(lhs, out); collect(rhs, out); }
+			}
+		}
+
+		let mut out: Vec<&Dice> = vec![];
+		collect(&command.expr, &mut out);
+		out
+	}
+
+	fn kind(s: &str) -> ParseErrorKind {
+		DiceCommand::from_str(s).unwrap_err().kind
+	}
+
+	#[test]
+	fn single() {
+		let input = "2d6";
+		let result = DiceCommand::from_str(input);
+		assert!(result.is_ok());
+
+		let command = result.unwrap();
+		assert_eq!(dice(&command).len(), 1);
+		assert_eq!(dice(&command)[0].count, 2);
+		assert_eq!(dice(&command)[0].kind, DieKind::Numeric(6));
+	}
+
+	#[test]
+	fn multi() {
+		let input = "2d6+1d8";
+		let result = DiceCommand::from_str(input);
+		assert!(result.is_ok());
+
+		let command = result.unwrap();
+		assert_eq!(dice(&command).len(), 2);
+		assert_eq!(dice(&command)[0].count, 2);
+		assert_eq!(dice(&command)[0].kind, DieKind::Numeric(6));
+		assert_eq!(dice(&command)[1].count, 1);
+		assert_eq!(dice(&command)[1].kind, DieKind::Numeric(8));
+	}
+
+	#[test]
+	fn add() {
+		let input = "2d6+1";
+		let result = DiceCommand::from_str(input);
+		assert!(result.is_ok());
+
+		let command = result.unwrap();
+		assert_eq!(dice(&command).len(), 1);
+		assert_eq!(dice(&command)[0].count, 2);
+		assert_eq!(dice(&command)[0].kind, DieKind::Numeric(6));
+		match command.expr {
+			Expr::Op(ArithOp::Add, _, rhs) => assert_eq!(*rhs, Expr::Num(1)),
+			other => panic!("Expected addition, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn precedence() {
+		let command = DiceCommand::from_str("1+2*3").unwrap();
+		assert_eq!(command.expr, Expr::Op(
+			ArithOp::Add,
+			Box::new(Expr::Num(1)),
+			Box::new(Expr::Op(ArithOp::Mul, Box::new(Expr::Num(2)), Box::new(Expr::Num(3))))
+		));
+	}
+
+	#[test]
+	fn parentheses() {
+		let command = DiceCommand::from_str("(2d6 + 3) * 2").unwrap();
+		match command.expr {
+			Expr::Op(ArithOp::Mul, lhs, rhs) => {
+				assert!(matches!(*lhs, Expr::Op(ArithOp::Add, _, _)));
+				assert_eq!(*rhs, Expr::Num(2));
+			}
+			other => panic!("Expected multiplication, got {:?}", other),
+		}
+
+		assert_eq!(kind("(1d6"), ParseErrorKind::Unclosed('('));
+	}
+
+	#[test]
+	fn division() {
+		let floor = DiceCommand::from_str("1d8/2").unwrap();
+		assert!(matches!(floor.expr, Expr::Op(ArithOp::Div, _, _)));
+
+		let ceil = DiceCommand::from_str("1d8/^2").unwrap();
+		assert!(matches!(ceil.expr, Expr::Op(ArithOp::DivCeil, _, _)));
+	}
+
+	#[test]
+	fn unary() {
+		let command = DiceCommand::from_str("-d20").unwrap();
+		match command.expr {
+			Expr::Neg(inner) => assert!(matches!(*inner, Expr::Dice(_))),
+			other => panic!("Expected negation, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn keep_drop() {
+		let command = DiceCommand::from_str("4d6kh3").unwrap();
+		assert_eq!(dice(&command)[0].args, vec![DiceArg::Keep(true, 3)]);
+
+		let command = DiceCommand::from_str("4d6dl1").unwrap();
+		assert_eq!(dice(&command)[0].kind, DieKind::Numeric(6));
+		assert_eq!(dice(&command)[0].args, vec![DiceArg::Drop(false, 1)]);
+
+		let command = DiceCommand::from_str("2d20kl1").unwrap();
+		assert_eq!(dice(&command)[0].args, vec![DiceArg::Keep(false, 1)]);
+
+		let command = DiceCommand::from_str("2d20k").unwrap();
+		assert_eq!(dice(&command)[0].args, vec![DiceArg::Keep(true, 1)]);
+
+		//	Plain 'd' after the sides is still disadvantage
+		let command = DiceCommand::from_str("1d20d").unwrap();
+		assert_eq!(dice(&command)[0].args, vec![DiceArg::Advantage(false)]);
+	}
+
+	#[test]
+	fn targets() {
+		let command = DiceCommand::from_str("10d10>=8").unwrap();
+		assert_eq!(dice(&command)[0].args, vec![DiceArg::Success(8, 10)]);
+
+		let command = DiceCommand::from_str("10d10>7f1").unwrap();
+		assert_eq!(dice(&command)[0].args, vec![DiceArg::Success(8, 10), DiceArg::Failure(1, 1)]);
+
+		let command = DiceCommand::from_str("6d6<=2f>=6").unwrap();
+		assert_eq!(dice(&command)[0].args, vec![DiceArg::Success(1, 2), DiceArg::Failure(6, 6)]);
+
+		let command = DiceCommand::from_str("5d10>=8!").unwrap();
+		assert_eq!(dice(&command)[0].args, vec![DiceArg::Extra(10, 10), DiceArg::Success(8, 10)]);
+
+		assert_eq!(kind("5d10>="), ParseErrorKind::UnexpectedEnd(Expected::Number));
+	}
+
+	#[test]
+	fn explode() {
+		let command = DiceCommand::from_str("1d6!!").unwrap();
+		assert_eq!(dice(&command)[0].args, vec![DiceArg::Compound(6, 6)]);
+
+		let command = DiceCommand::from_str("1d6!p").unwrap();
+		assert_eq!(dice(&command)[0].args, vec![DiceArg::Penetrate(6, 6)]);
+
+		let command = DiceCommand::from_str("3d6!>=5").unwrap();
+		assert_eq!(dice(&command)[0].args, vec![DiceArg::Extra(5, 6)]);
+
+		let command = DiceCommand::from_str("1d10!!>8").unwrap();
+		assert_eq!(dice(&command)[0].args, vec![DiceArg::Compound(9, 10)]);
+	}
+
+	#[test]
+	fn kinds() {
+		let command = DiceCommand::from_str("4dF+d%").unwrap();
+		assert_eq!(dice(&command)[0].kind, DieKind::Fate);
+		assert_eq!(dice(&command)[1].kind, DieKind::Numeric(100));
+
+		let command = DiceCommand::from_str("1d{1,1,2,3,5,8}!").unwrap();
+		assert_eq!(dice(&command)[0].kind.faces(), 6);
+		assert_eq!(dice(&command)[0].args, vec![DiceArg::Extra(8, 8)]);
+
+		let command = DiceCommand::from_str("2d{hit, miss, crit}").unwrap();
+		assert_eq!(dice(&command)[0].kind, DieKind::Custom(vec![
+			Side::Symbol("hit".to_owned()),
+			Side::Symbol("miss".to_owned()),
+			Side::Symbol("crit".to_owned()),
+		]));
+
+		//	Fate dice start at -1, so "<=0" includes the minus face
+		let command = DiceCommand::from_str("4dF<=0").unwrap();
+		assert_eq!(dice(&command)[0].args, vec![DiceArg::Success(-1, 0)]);
+
+		assert_eq!(kind("1d{}"), ParseErrorKind::NoFaces);
+		assert_eq!(kind("1d{1,2"), ParseErrorKind::Unclosed('{'));
+		assert_eq!(kind("1d0"), ParseErrorKind::ZeroSides);
+	}
+
+	#[test]
+	fn errors() {
+		let err = DiceCommand::from_str("2d6q").unwrap_err();
+		assert_eq!(err.kind, ParseErrorKind::Unexpected("q".to_owned(), Expected::Modifier));
+		assert_eq!(err.span, Span{ start: 3, end: 4 });
+		assert!(err.to_string().starts_with("unexpected `q` at column 4: expected a modifier"));
+		assert!(err.to_string().ends_with("2d6q\n   ^\n```"));
+
+		assert_eq!(kind("2d"), ParseErrorKind::UnexpectedEnd(Expected::Sides));
+		assert_eq!(kind("2d+1"), ParseErrorKind::Unexpected("+".to_owned(), Expected::Sides));
+		assert_eq!(kind("2d6+"), ParseErrorKind::UnexpectedEnd(Expected::Expression));
+		assert_eq!(kind("2 3"), ParseErrorKind::Unexpected("3".to_owned(), Expected::Operator));
+		assert_eq!(kind("(1d6 2)"), ParseErrorKind::Unexpected("2".to_owned(), Expected::Close));
+		assert_eq!(kind("1d6f"), ParseErrorKind::UnexpectedEnd(Expected::Number));
+		assert_eq!(kind(""), ParseErrorKind::UnexpectedEnd(Expected::Expression));
+		assert_eq!(kind("1 + $"), ParseErrorKind::Unexpected("$".to_owned(), Expected::Name));
+
+		let err = DiceCommand::from_str("1d99999999999").unwrap_err();
+		assert_eq!(err.kind, ParseErrorKind::NumberTooLarge);
+		assert_eq!(err.span, Span{ start: 2, end: 13 });
+
+		//	Bare "d20" is a single die
+		assert_eq!(dice(&DiceCommand::from_str("d20").unwrap())[0].count, 1);
+	}
+
+	#[test]
+	fn limits() {
+		let limits = RollLimits{ max_dice: 10, max_sides: 20, max_terms: 3, ..RollLimits::default() };
+		let parse = |s: &str| DiceCommand::parse_with(s, &limits).map_err(|e| e.kind);
+
+		assert!(parse("10d20").is_ok());
+		assert_eq!(parse("4d6 + 7d6"), Err(ParseErrorKind::TooManyDice(10)));
+		assert_eq!(parse("2000000000d6"), Err(ParseErrorKind::TooManyDice(10)));
+		assert_eq!(parse("1d21"), Err(ParseErrorKind::TooManySides(20)));
+		assert_eq!(parse("1d%"), Err(ParseErrorKind::TooManySides(20)));
+		assert_eq!(parse("1 + 2 + (3 + 4)"), Err(ParseErrorKind::TooManyTerms(3)));
+
+		let err = DiceCommand::parse_with("1d6 + 20d6", &limits).unwrap_err();
+		assert_eq!(err.span, Span{ start: 6, end: 8 });
+		assert_eq!(DiceCommand::parse_with("1d6!", &limits).unwrap().max_explosions, limits.max_explosions);
+
+		let mut limits = RollLimits::default();
+		assert_eq!(limits.set("max_output", "2000"), Ok(()));
+		assert_eq!(limits.max_output, 2000);
+		assert!(limits.set("max_output", "2001").is_err());
+		assert!(limits.set("max_dice", "0").is_err());
+		assert!(limits.set("max_dice", "1000000").is_err());
+		assert!(limits.set("max_sides", "4294967295").is_err());
+		assert!(limits.set("max_wands", "1").is_err());
+	}
+
+	#[test]
+	fn repeat() {
+		let limits = RollLimits::default();
+		let (repeat, command) = DiceCommand::parse_repeated("6x 4d6kh3", &limits).unwrap();
+		assert_eq!((repeat, command.to_string()), (6, "4d6kh3".to_owned()));
+
+		let (repeat, command) = DiceCommand::parse_repeated("3# 1d20+5", &limits).unwrap();
+		assert_eq!((repeat, command.to_string()), (3, "1d20 + 5".to_owned()));
+
+		//	Without the prefix it's a normal roll, and the prefix means nothing elsewhere
+		assert_eq!(DiceCommand::parse_repeated("1d6x6", &limits).unwrap().0, 1);
+		assert_eq!(kind("6x 4d6"), ParseErrorKind::Unexpected("x".to_owned(), Expected::Operator));
+
+		let err = DiceCommand::parse_repeated("0x 1d6", &limits).unwrap_err();
+		assert_eq!((err.kind, err.span), (ParseErrorKind::BadRepeat(limits.max_repeats), Span{ start: 0, end: 2 }));
+		assert!(DiceCommand::parse_repeated("1000# 1d6", &limits).is_err());
+
+		//	Apart from right after a leading number, '#' starts a comment
+		let (repeat, command) = DiceCommand::parse_repeated("2# 1d20 # attacks", &limits).unwrap();
+		assert_eq!((repeat, command.to_string()), (2, "1d20 # attacks".to_owned()));
+		let (repeat, command) = DiceCommand::parse_repeated("5 # flat", &limits).unwrap();
+		assert_eq!((repeat, command.to_string()), (1, "5 # flat".to_owned()));
+	}
+
+	#[test]
+	fn labels() {
+		let command = DiceCommand::from_str("2d6[slashing] + 1d6[fire] + 3").unwrap();
+		let labels: Vec<Option<&str>> = dice(&command).iter().map(|die| die.label.as_deref()).collect();
+		assert_eq!(labels, [Some("slashing"), Some("fire")]);
+
+		assert_eq!(kind("2d6[]"), ParseErrorKind::NoLabel);
+		assert_eq!(kind("2d6[fire"), ParseErrorKind::Unclosed('['));
+		assert_eq!(kind("5[fire]"), ParseErrorKind::Unexpected("[fire]".to_owned(), Expected::Operator));
+		assert_eq!(kind("1d6[a][b]"), ParseErrorKind::Unexpected("[b]".to_owned(), Expected::Modifier));
+	}
+
+	#[test]
+	fn round_trip() {
+		let cases = [
+			("d20", "1d20"),
+			("4d6kh3 + 2", "4d6kh3 + 2"),
+			("2d20a", "2d20a"),
+			("1d6x6r1..2", "1d6x6r1..2"),
+			("3d6!!", "3d6!!=6"),
+			("5d10>=8f1", "5d10>=8f=1"),
+			("4dF<0", "4dF=-1"),
+			("1d{-5}!", "1d{-5}!=-5"),
+			("4dF!>=-1", "4dF!>=-1"),
+			("1d{-5,-3}!<=-4", "1d{-5,-3}!<=-4"),
+			("1d200x150..200", "1d200x150..200"),
+			("1d{1, hit ,3}kl1", "1d{1,hit,3}kl1"),
+			("d%dh1", "1d100dh1"),
+			("1d6aad", "1d6a"),
+			("-(1 + 2) * 3 - (4 - 5) /^ 2", "-(1 + 2) * 3 - (4 - 5) /^ 2"),
+			("((1))+2*3", "1 + 2 * 3"),
+			("(1 + 2) * 3", "(1 + 2) * 3"),
+			("$attack+$dmg_2", "$attack + $dmg_2"),
+			("1d20+@str+@prof", "1d20 + @str + @prof"),
+			("2d6[slashing]+1d6[ fire ]", "2d6[slashing] + 1d6[fire]"),
+			("1d20+5 #  longsword ", "1d20 + 5 # longsword"),
+			("4d6kh3[str] # stats", "4d6kh3[str] # stats"),
+			("1d6 #", "1d6"),
+		];
+
+		for (input, canonical) in cases {
+			let command = DiceCommand::from_str(input).unwrap();
+			assert_eq!(command.to_string(), canonical);
+			assert_eq!(DiceCommand::from_str(canonical).unwrap(), command, "{}", input);
+		}
+
+		//	Explosion ranges aren't cut off below zero or above a hundred
+		let command = DiceCommand::from_str("4dF!>=-1").unwrap();
+		assert_eq!(dice(&command)[0].args, [DiceArg::Extra(-1, 1)]);
+
+		let command = DiceCommand::from_str("8d6!p>=5 - 1d4").unwrap();
+		let json = serde_json::to_string(&command).unwrap();
+		assert_eq!(json, "\"8d6!p>=5 - 1d4\"");
+		assert_eq!(serde_json::from_str::<DiceCommand>(&json).unwrap(), command);
+		assert!(serde_json::from_str::<DiceCommand>("\"1d\"").is_err());
+	}
+
+	#[test]
+	fn inline() {
+		assert_eq!(inline_rolls("i swing [[1d20+5]] and hit for [[ 2d6 ]]!"), vec!["1d20+5", "2d6"]);
+		assert_eq!(inline_rolls("[[1d6]][[2d6]]"), vec!["1d6", "2d6"]);
+		assert_eq!(inline_rolls("no rolls [[ ]] or [[unclosed"), Vec::<&str>::new());
+		assert_eq!(inline_rolls("[[[1d4]]]"), vec!["[1d4]"]);
+		assert_eq!(inline_rolls("[[2d6[fire] + 1d4[cold]]] burns"), vec!["2d6[fire] + 1d4[cold]"]);
+	}
+
+	#[test]
+	fn comments() {
+		assert_eq!(split_comment("1d20 --seed 5 # attack --sort"), ("1d20 --seed 5 ", "# attack --sort"));
+		assert_eq!(split_comment("2# 1d20 # hidden"), ("2# 1d20 ", "# hidden"));
+		assert_eq!(split_comment(" 12#1d6"), (" 12#1d6", ""));
+		assert_eq!(split_comment("1d6[#1] + 2 #"), ("1d6[#1] + 2 ", "#"));
+		assert_eq!(split_comment("5 # flat"), ("5 ", "# flat"));
+	}
+
+	#[test]
+	fn never_panics() {
+		use rand::{Rng, SeedableRng, rngs::StdRng};
+
+		let alphabet: Vec<char> = "0123456789d%F{},.+-*/^()[]#akhlxrp!<>=f q\u{e9}".chars().collect();
+		let mut rng = StdRng::seed_from_u64(9);
+		for _ in 0..20_000 {
+			let len = rng.gen_range(0..12);
+			let input: String = (0..len).map(|_| alphabet[rng.gen_range(0..alphabet.len())]).collect();
+
+			match DiceCommand::from_str(&input) {
+				Ok(command) => assert_eq!(DiceCommand::from_str(&command.to_string()), Ok(command), "{}", input),
+				Err(e) => {
+					assert!(e.span.start <= input.chars().count());
+					let _ = e.to_string();
+				}
+			}
+		}
+	}
+
+	/*
+	#[test]
+	fn advantage() {
+		let input = "2d6a";
+		let result = DiceCommand::from_str(input);
+		assert!(result.is_ok());
+
+		let command = result.unwrap();
+		assert_eq!(command.dice.len(), 1);
+		assert_eq!(command.dice[0].args, vec![DiceArg::Advantage(true)]);
+	}
+
+	#[test]
+	fn reroll() {
+		let input = "2d6r..2";
+		let result = DiceCommand::from_str(input);
+		assert!(result.is_ok());
+
+		let command = result.unwrap();
+		assert_eq!(command.dice.len(), 1);
+		assert_eq!(command.dice[0].args, vec![DiceArg::Reroll(0, 2)]);
+	}
+
+	#[test]
+	fn extra() {
+		let input = "2d6x4..";
+		let result = DiceCommand::from_str(input);
+		assert!(result.is_ok());
+
+		let command = result.unwrap();
+		assert_eq!(command.dice.len(), 1);
+		assert_eq!(command.dice[0].args, vec![DiceArg::Extra(0, 4)]);
+	}
+
+	#[test]
+	fn invalid() {
+		let input = "invalid";
+		let result = DiceCommand::from_str(input);
+		assert!(result.is_err());
+
+		match result.err().unwrap() {
+			ParseRollError::UnrecognizedOp(op) => assert_eq!(op, "invalid"),
+			_ => panic!("Expected UnrecognizedOp error"),
+		}
+	}
+	// */
+}
@@ -0,0 +1,243 @@
+//		Imports
+use std::{
+	collections::HashMap,
+	fmt,
+};
+
+use super::{
+	DiceCommand, Expr, RollLimits
+};
+
+//		Data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResolveError {
+	UnknownMacro(String),
+	UnknownVar(String),
+	//	The chain of macros that led back to where it started
+	Cycle(Vec<String>),
+	TooManyTerms(u32),
+	TooManyDice(u32),
+	TooManySides(u32),
+}
+
+impl fmt::Display for ResolveError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::UnknownMacro(name) => write!(f, "there's no macro called ${}", name),
+			Self::UnknownVar(name) => write!(f, "@{} isn't set; try `!var set {} <value>`", name, name),
+			Self::Cycle(chain) => {
+				let chain: Vec<String> = chain.iter().map(|name| format!("${}", name)).collect();
+				write!(f, "macros can't refer back to themselves: {}", chain.join(" → "))
+			}
+			Self::TooManyTerms(max) =>
+				write!(f, "that roll is too long once its macros are filled in: rolls here can have at most {} terms", max),
+			Self::TooManyDice(max) =>
+				write!(f, "that roll has too many dice once its macros are filled in: rolls here can use at most {}", max),
+			Self::TooManySides(max) =>
+				write!(f, "that roll has too many sides once its macros are filled in: dice here can have at most {}", max),
+		}
+	}
+}
+
+//	Where names in a roll are looked up. Macro layers are searched in order, so a
+//	user's own macros can shadow the guild's. Variables belong to whoever is rolling.
+#[derive(Debug, Clone, Default)]
+pub struct Scope<'a> {
+	pub macros:Vec<&'a HashMap<String, DiceCommand>>,
+	pub vars:Option<&'a HashMap<String, i32>>
+}
+
+//		Functions
+impl Scope<'_> {
+	pub fn find_macro(&self, name: &str) -> Option<&DiceCommand> {
+		self.macros.iter().find_map(|layer| layer.get(name))
+	}
+}
+
+impl DiceCommand {
+	//	Fills in every macro & variable the roll refers to, checking the result against
+	//	the same limits the parser uses. Saved macros are only checked here.
+	pub fn resolve(&self, scope: &Scope, limits: &RollLimits) -> Result<DiceCommand, ResolveError> {
+		let mut resolver = Resolver{ scope, limits, stack: vec![], terms: 0, dice: 0 };
+		let expr = resolver.expr(&self.expr)?;
+
+		//	A roll that's just a macro takes on the macro's comment
+		let comment = match (&self.comment, &self.expr) {
+			(None, Expr::Macro(name)) => scope.find_macro(name).and_then(|body| body.comment.clone()),
+			(comment, _) => comment.clone()
+		};
+
+		Ok(DiceCommand{ expr, max_explosions: self.max_explosions, comment })
+	}
+}
+
+struct Resolver<'a> {
+	scope:&'a Scope<'a>,
+	limits:&'a RollLimits,
+
+	//	Macros currently being filled in, innermost last
+	stack:Vec<String>,
+	terms:u32,
+	dice:u32
+}
+
+impl Resolver<'_> {
+	//	Copies the tree, counting as it goes so a macro that uses another many times
+	//	over can't blow up before the limits notice.
+	fn expr(&mut self, expr: &Expr) -> Result<Expr, ResolveError> {
+		Ok(match expr {
+			Expr::Num(_) | Expr::Dice(_) => {
+				self.terms += 1;
+				if self.terms > self.limits.max_terms {
+					return Err(ResolveError::TooManyTerms(self.limits.max_terms))
+				}
+
+				if let Expr::Dice(dice) = expr {
+					self.dice = self.dice.saturating_add(dice.count as u32);
+					if self.dice > self.limits.max_dice {
+						return Err(ResolveError::TooManyDice(self.limits.max_dice))
+					}
+					if dice.kind.faces() > self.limits.max_sides as usize {
+						return Err(ResolveError::TooManySides(self.limits.max_sides))
+					}
+				}
+
+				expr.clone()
+			}
+			Expr::Var(name) => match self.scope.vars.and_then(|vars| vars.get(name)).copied() {
+				//	Written the way the parser would have read it
+				Some(value) if value < 0 && value != i32::MIN =>
+					Expr::Neg(Box::new(self.expr(&Expr::Num(-value))?)),
+				Some(value) => self.expr(&Expr::Num(value))?,
+				None => return Err(ResolveError::UnknownVar(name.clone()))
+			},
+			Expr::Neg(inner) => Expr::Neg(Box::new(self.expr(inner)?)),
+			Expr::Op(op, lhs, rhs) => Expr::Op(*op, Box::new(self.expr(lhs)?), Box::new(self.expr(rhs)?)),
+			Expr::Macro(name) => {
+				if let Some(start) = self.stack.iter().position(|n| n == name) {
+					let mut chain = self.stack[start..].to_vec();
+					chain.push(name.clone());
+					return Err(ResolveError::Cycle(chain))
+				}
+
+				let Some(body) = self.scope.find_macro(name) else {
+					return Err(ResolveError::UnknownMacro(name.clone()))
+				};
+
+				self.stack.push(name.clone());
+				let out = self.expr(&body.expr)?;
+				self.stack.pop();
+
+				out
+			}
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::str::FromStr;
+	use twilight_model::id::Id;
+	use crate::data::UserData;
+
+	fn macros(defs: &[(&str, &str)]) -> HashMap<String, DiceCommand> {
+		defs.iter()
+			.map(|(name, body)| (name.to_string(), DiceCommand::from_str(body).unwrap()))
+			.collect()
+	}
+
+	fn resolve(expr: &str, scope: &Scope) -> Result<String, ResolveError> {
+		DiceCommand::from_str(expr).unwrap()
+			.resolve(scope, &RollLimits::default())
+			.map(|command| command.to_string())
+	}
+
+	#[test]
+	fn nested() {
+		let guild = macros(&[("attack", "1d20 + $bonus"), ("bonus", "7"), ("damage", "2d6")]);
+		let user = macros(&[("bonus", "5")]);
+		let scope = Scope{ macros: vec![&user, &guild], ..Default::default() };
+
+		//	The user's $bonus wins over the guild's
+		assert_eq!(resolve("$attack", &scope), Ok("1d20 + 5".to_owned()));
+		assert_eq!(resolve("$damage * 2", &scope), Ok("2d6 * 2".to_owned()));
+		assert_eq!(resolve("$missing", &scope), Err(ResolveError::UnknownMacro("missing".to_owned())));
+
+		//	A lone macro brings its comment along, unless the roll has its own
+		let sword = macros(&[("sword", "1d8[slashing] + 3 # longsword")]);
+		let scope = Scope{ macros: vec![&sword], ..Default::default() };
+		assert_eq!(resolve("$sword", &scope), Ok("1d8[slashing] + 3 # longsword".to_owned()));
+		assert_eq!(resolve("$sword # smite", &scope), Ok("1d8[slashing] + 3 # smite".to_owned()));
+		assert_eq!(resolve("$sword + 2", &scope), Ok("1d8[slashing] + 3 + 2".to_owned()));
+
+		//	Brackets come from the tree, not the text of the macro
+		let sum = macros(&[("sum", "1 + 2")]);
+		let scope = Scope{ macros: vec![&sum], ..Default::default() };
+		assert_eq!(resolve("$sum * 3", &scope), Ok("(1 + 2) * 3".to_owned()));
+	}
+
+	#[test]
+	fn vars() {
+		let vars: HashMap<String, i32> = [("str", 3), ("prof", 2), ("dex", -1)]
+			.map(|(name, value)| (name.to_owned(), value))
+			.into_iter()
+			.collect();
+		let attack = macros(&[("attack", "1d20 + @str + @prof")]);
+		let scope = Scope{ macros: vec![&attack], vars: Some(&vars) };
+
+		assert_eq!(resolve("$attack", &scope), Ok("1d20 + 3 + 2".to_owned()));
+		assert_eq!(resolve("1d20 + @dex", &scope), Ok("1d20 + -1".to_owned()));
+		assert_eq!(resolve("@wis", &scope), Err(ResolveError::UnknownVar("wis".to_owned())));
+		assert_eq!(resolve("@str", &Scope::default()), Err(ResolveError::UnknownVar("str".to_owned())));
+	}
+
+	#[test]
+	fn cycles() {
+		let defs = macros(&[("a", "$b + 1"), ("b", "$c"), ("c", "$a"), ("d", "$d"), ("e", "$b")]);
+		let scope = Scope{ macros: vec![&defs], ..Default::default() };
+
+		assert_eq!(resolve("$a", &scope), Err(ResolveError::Cycle(
+			["a", "b", "c", "a"].map(str::to_owned).to_vec()
+		)));
+		assert_eq!(resolve("$d", &scope), Err(ResolveError::Cycle(vec!["d".to_owned(), "d".to_owned()])));
+		assert_eq!(resolve("$e", &scope), Err(ResolveError::Cycle(
+			["b", "c", "a", "b"].map(str::to_owned).to_vec()
+		)));
+	}
+
+	#[test]
+	fn limits() {
+		//	Each level doubles the one below it
+		let mut defs = vec![("m0".to_owned(), "1d6".to_owned())];
+		for i in 1..40 {
+			defs.push((format!("m{}", i), format!("$m{} + $m{}", i - 1, i - 1)));
+		}
+		let defs: Vec<(&str, &str)> = defs.iter().map(|(n, b)| (n.as_str(), b.as_str())).collect();
+		let defs = macros(&defs);
+
+		let scope = Scope{ macros: vec![&defs], ..Default::default() };
+		assert_eq!(resolve("$m39", &scope), Err(ResolveError::TooManyTerms(RollLimits::default().max_terms)));
+		assert!(resolve("$m6", &scope).is_ok());
+	}
+
+	#[test]
+	fn saved_under_raised_limits() {
+		let raised = RollLimits{ max_dice: 5000, max_sides: 100_000, ..RollLimits::default() };
+		let macros: HashMap<String, DiceCommand> = [("horde", "2000d6"), ("huge", "1d50000")].iter()
+			.map(|(name, body)| (name.to_string(), DiceCommand::parse_with(body, &raised).unwrap()))
+			.collect();
+		let user = UserData{ id: Id::new(1), macros: macros.clone(), vars: HashMap::new() };
+
+		//	Loading them back doesn't hold them to the default limits
+		let json = serde_json::to_string(&user).unwrap();
+		let loaded: UserData = serde_json::from_str(&json).unwrap();
+		assert_eq!(loaded.macros, macros);
+
+		//	Those still apply when they're rolled
+		let scope = Scope{ macros: vec![&loaded.macros], ..Default::default() };
+		assert!(DiceCommand::from_str("$horde + $huge").unwrap().resolve(&scope, &raised).is_ok());
+		assert_eq!(resolve("$horde", &scope), Err(ResolveError::TooManyDice(1000)));
+		assert_eq!(resolve("$huge", &scope), Err(ResolveError::TooManySides(10_000)));
+	}
+}
@@ -0,0 +1,920 @@
+//		Imports
+use std::{
+	collections::BTreeMap,
+	fmt, cmp::{min, max},
+};
+use rand::Rng;
+
+use super::{
+	DiceCommand, Expr, Dice, DieKind, Side, DiceArg, ArithOp
+};
+
+//		Data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RollError {
+	DivideByZero,
+	Overflow,
+	Unresolved(String),
+}
+
+impl fmt::Display for RollError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::DivideByZero => write!(f, "Can't divide by zero"),
+			Self::Overflow => write!(f, "That roll came out too big to count"),
+			Self::Unresolved(name) => write!(f, "{} has to be filled in before rolling", name),
+		}
+	}
+}
+
+//	The outcome of rolling a DiceCommand. Mirrors the shape of the expression, with
+//	every dice term expanded into the individual dice that were rolled for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RollResult {
+	pub total:i32,
+	pub successes:Option<i32>,
+	node:RollNode,
+	comment:Option<String>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum RollNode {
+	Num(i32),
+	Dice(DiceRoll),
+	Neg(Box<RollNode>),
+	Op(ArithOp, Box<RollNode>, Box<RollNode>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DiceRoll {
+	count:i32,
+	kind:DieKind,
+	label:Option<String>,
+
+	dice:Vec<DieRoll>,
+	total:i32,
+	successes:Option<i32>
+}
+
+//	One die of a pool. `faces` is the reroll chain - the first entry is the original
+//	roll, the last is the one that counts. Dice added by an explosion hang off the
+//	die that caused them; compounded ones are added onto it rather than joining the pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct DieRoll {
+	faces:Vec<Face>,
+	exploded:Vec<DieRoll>,
+	exploding:bool,
+	compounded:bool,
+	//	Subtracted from the face, for penetrating explosions
+	penalty:i32,
+
+	kept:bool,
+	subtotal:i32,
+	success:Option<bool>
+}
+
+//	A single face (by position on the die, and its value) along with the face
+//	advantage/disadvantage passed over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Face {
+	side:usize,
+	value:i32,
+	unchosen:Option<usize>
+}
+
+//		Functions
+impl DiceCommand {
+	//	Generic over the source of randomness so rolls can be replayed from a seed.
+	pub fn roll<R: Rng + ?Sized>(&self, rng: &mut R) -> Result<RollResult, RollError> {
+		let (node, total) = self.expr.eval(rng, self.max_explosions)?;
+		let successes = node.successes();
+
+		Ok(RollResult{ total, successes, node, comment: self.comment.clone() })
+	}
+}
+
+impl Expr {
+	fn eval(&self, rng: &mut (impl Rng + ?Sized), max_explosions: u32) -> Result<(RollNode, i32), RollError> {
+		Ok(match self {
+			Expr::Num(n) => (RollNode::Num(*n), *n),
+			Expr::Dice(die) => {
+				//	Success pools contribute their count rather than their sum
+				let roll = die.roll(rng, max_explosions)?;
+				let value = roll.successes.unwrap_or(roll.total);
+				(RollNode::Dice(roll), value)
+			}
+			Expr::Macro(name) => return Err(RollError::Unresolved(format!("${}", name))),
+			Expr::Var(name) => return Err(RollError::Unresolved(format!("@{}", name))),
+			Expr::Neg(inner) => {
+				let (node, value) = inner.eval(rng, max_explosions)?;
+				(RollNode::Neg(Box::new(node)), value.checked_neg().ok_or(RollError::Overflow)?)
+			}
+			Expr::Op(op, lhs, rhs) => {
+				let (lhs, lhs_value) = lhs.eval(rng, max_explosions)?;
+				let (rhs, rhs_value) = rhs.eval(rng, max_explosions)?;
+				(RollNode::Op(*op, Box::new(lhs), Box::new(rhs)), op.apply(lhs_value, rhs_value)?)
+			}
+		})
+	}
+}
+
+impl ArithOp {
+	pub(super) fn apply(self, lhs: i32, rhs: i32) -> Result<i32, RollError> {
+		match self {
+			ArithOp::Add => lhs.checked_add(rhs).ok_or(RollError::Overflow),
+			ArithOp::Sub => lhs.checked_sub(rhs).ok_or(RollError::Overflow),
+			ArithOp::Mul => lhs.checked_mul(rhs).ok_or(RollError::Overflow),
+			ArithOp::Div | ArithOp::DivCeil => {
+				if rhs == 0 { return Err(RollError::DivideByZero) }
+
+				//	Integer division truncates towards zero, so nudge inexact results
+				//	towards whichever side was asked for. i32::MIN / -1 is the one
+				//	quotient that doesn't fit.
+				let quot = lhs.checked_div(rhs).ok_or(RollError::Overflow)?;
+				let inexact = lhs % rhs != 0;
+				let negative = (lhs < 0) != (rhs < 0);
+
+				Ok(match self {
+					ArithOp::Div if inexact && negative => quot - 1,
+					ArithOp::DivCeil if inexact && !negative => quot + 1,
+					_ => quot
+				})
+			}
+		}
+	}
+
+	pub(super) fn precedence(self) -> u8 {
+		match self {
+			ArithOp::Add | ArithOp::Sub => 1,
+			ArithOp::Mul | ArithOp::Div | ArithOp::DivCeil => 2,
+		}
+	}
+}
+
+impl Dice {
+	fn roll(&self, rng: &mut (impl Rng + ?Sized), max_explosions: u32) -> Result<DiceRoll, RollError> {
+		let compound = self.args.iter().any(|a| matches!(a, DiceArg::Compound(..)));
+		let penalty = if self.args.iter().any(|a| matches!(a, DiceArg::Penetrate(..))) { 1 } else { 0 };
+
+		let mut dice: Vec<DieRoll> = vec![];
+		for _ in 0..self.count {
+			let mut die = self.roll_die(rng);
+			die.compounded = compound;
+
+			//	Explosions go off the face itself, before any penetration penalty
+			let mut last = die.face();
+			while die.exploded.len() < max_explosions as usize && self.explodes(last) {
+				match die.exploded.last_mut() {
+					Some(prev) => prev.exploding = true,
+					None => die.exploding = true,
+				}
+
+				let mut extra = self.roll_die(rng);
+				extra.penalty = penalty;
+				last = extra.face();
+				die.exploded.push(extra);
+			}
+
+			dice.push(die);
+		}
+
+		//	Extra dice are part of the pool, so they can be kept or dropped like any other
+		let values: Vec<i32> = pool(&dice).map(|die| die.value()).collect();
+		let kept = self.kept(&values);
+
+		let mut index: usize = 0;
+		let mut total: i32 = 0;
+		let mut successes: i32 = 0;
+		for die in &mut dice {
+			die.tally(kept[index], self.success(die.value()), &mut total, &mut successes)?;
+			index += 1;
+
+			if die.compounded { continue }
+			for extra in &mut die.exploded {
+				extra.tally(kept[index], self.success(extra.value()), &mut total, &mut successes)?;
+				index += 1;
+			}
+		}
+
+		Ok(DiceRoll{
+			count: self.count,
+			kind: self.kind.clone(),
+			label: self.label.clone(),
+			dice,
+			total,
+			successes: self.counts_successes().then_some(successes)
+		})
+	}
+
+	//	A die with its reroll chain, but not any explosions.
+	fn roll_die(&self, rng: &mut (impl Rng + ?Sized)) -> DieRoll {
+		let mut faces: Vec<Face> = vec![self.roll_face(rng)];
+
+		if self.args.iter().any(|a|
+			matches!(a, DiceArg::Reroll(l, r)
+			if (*l..=*r).contains(&faces[0].value)))
+		{ faces.push(self.roll_face(rng)); }
+
+		DieRoll{
+			faces,
+			exploded: vec![],
+			exploding: false,
+			compounded: false,
+			penalty: 0,
+			kept: true,
+			subtotal: 0,
+			success: None
+		}
+	}
+
+	//	A single face, with advantage applied - rerolls go through here too.
+	fn roll_face(&self, rng: &mut (impl Rng + ?Sized)) -> Face {
+		let side: usize = rng.gen_range(0..self.kind.faces());
+		let value: i32 = self.kind.value(side);
+
+		match self.args.iter().find(|&a| matches!(a, DiceArg::Advantage(_))) {
+			Some(DiceArg::Advantage(p)) => {
+				let side2: usize = rng.gen_range(0..self.kind.faces());
+				let value2: i32 = self.kind.value(side2);
+
+				let best = if *p { max(value, value2) } else { min(value, value2) };
+				match best == value {
+					true => Face{ side, value, unchosen: Some(side2) },
+					false => Face{ side: side2, value: value2, unchosen: Some(side) },
+				}
+			}
+			_ => Face{ side, value, unchosen: None }
+		}
+	}
+
+	pub(super) fn explodes(&self, value: i32) -> bool {
+		self.args.iter().any(|a|
+			matches!(a, DiceArg::Extra(l, r) | DiceArg::Compound(l, r) | DiceArg::Penetrate(l, r)
+			if (*l..=*r).contains(&value)))
+	}
+
+	fn counts_successes(&self) -> bool {
+		self.args.iter().any(|a| matches!(a, DiceArg::Success(..) | DiceArg::Failure(..)))
+	}
+
+	//	Whether a face is a success (true), a failure (false) or neither.
+	pub(super) fn success(&self, value: i32) -> Option<bool> {
+		let in_range = |a: &DiceArg| match a {
+			DiceArg::Success(l, r) | DiceArg::Failure(l, r) => (*l..=*r).contains(&value),
+			_ => false
+		};
+
+		if self.args.iter().any(|a| matches!(a, DiceArg::Success(..)) && in_range(a)) {
+			Some(true)
+		} else if self.args.iter().any(|a| matches!(a, DiceArg::Failure(..)) && in_range(a)) {
+			Some(false)
+		} else { None }
+	}
+
+	//	Which of the rolled values survive keep/drop modifiers.
+	fn kept(&self, rolls: &[i32]) -> Vec<bool> {
+		let pool = rolls.len() as i32;
+		let (highest, n) = match self.args.iter().find(|&a| matches!(a, DiceArg::Keep(..) | DiceArg::Drop(..))) {
+			Some(DiceArg::Keep(highest, n)) => (*highest, *n),
+			Some(DiceArg::Drop(highest, n)) => (!*highest, pool - n),
+			_ => return vec![true; rolls.len()]
+		};
+
+		//	Stable sort, so ties are broken in favour of the earlier die
+		let mut order: Vec<usize> = (0..rolls.len()).collect();
+		order.sort_by(|&a, &b| match highest {
+			true => rolls[b].cmp(&rolls[a]),
+			false => rolls[a].cmp(&rolls[b])
+		});
+
+		let mut kept = vec![false; rolls.len()];
+		for &i in order.iter().take(n.clamp(0, pool) as usize) {
+			kept[i] = true;
+		}
+
+		kept
+	}
+}
+
+//	Every die that counts as its own member of the pool, in the order rolled.
+fn pool(dice: &[DieRoll]) -> impl Iterator<Item = &DieRoll> {
+	dice.iter().flat_map(|die| {
+		let extras: &[DieRoll] = if die.compounded { &[] } else { &die.exploded };
+		std::iter::once(die).chain(extras.iter())
+	})
+}
+
+impl DieRoll {
+	//	The face that was rolled, before penalties or compounding.
+	fn face(&self) -> i32 {
+		self.faces.last().map(|face| face.value).unwrap_or(0)
+	}
+
+	//	Saturates rather than overflowing; anything that big gets caught when it's
+	//	added to the total.
+	fn value(&self) -> i32 {
+		let compounded: i32 = match self.compounded {
+			true => self.exploded.iter().fold(0, |sum, extra| sum.saturating_add(extra.value())),
+			false => 0
+		};
+
+		self.face().saturating_sub(self.penalty).saturating_add(compounded)
+	}
+
+	//	Marks the die kept or dropped and adds it to the running totals of its term.
+	//	Dropped dice don't count towards successes either.
+	fn tally(&mut self, kept: bool, success: Option<bool>, total: &mut i32, successes: &mut i32) -> Result<(), RollError> {
+		if kept {
+			*total = total.checked_add(self.value()).ok_or(RollError::Overflow)?;
+			*successes += match success { Some(true) => 1, Some(false) => -1, None => 0 };
+		}
+
+		self.kept = kept;
+		self.subtotal = *total;
+		self.success = if kept { success } else { None };
+
+		Ok(())
+	}
+}
+
+impl RollResult {
+	//	The natural faces of every kept d`sides`, in the order rolled, for game systems
+	//	that care what came up as well as the total.
+	pub fn kept_faces(&self, sides: i32) -> Vec<i32> {
+		let mut faces = vec![];
+		self.node.kept_faces(sides, &mut faces);
+		faces
+	}
+
+	//	The total of each label's dice, in the order the labels first appear. Each term
+	//	counts as it came up, before any arithmetic around it.
+	pub fn labels(&self) -> Vec<(&str, i32)> {
+		let mut labels = vec![];
+		self.node.labels(&mut labels);
+		labels
+	}
+
+	//	Every face the dice generator came up with, by die size - rerolled, dropped and
+	//	passed-over ones too. What an audit checks for bias.
+	pub fn rolled_faces(&self, faces: &mut BTreeMap<i32, Vec<i32>>) {
+		self.node.rolled_faces(faces);
+	}
+}
+
+impl RollNode {
+	fn kept_faces(&self, sides: i32, faces: &mut Vec<i32>) {
+		match self {
+			RollNode::Num(_) => {}
+			RollNode::Dice(roll) => if roll.kind == DieKind::Numeric(sides) {
+				faces.extend(pool(&roll.dice).filter(|die| die.kept).map(|die| die.face()));
+			},
+			RollNode::Neg(inner) => inner.kept_faces(sides, faces),
+			RollNode::Op(_, lhs, rhs) => {
+				lhs.kept_faces(sides, faces);
+				rhs.kept_faces(sides, faces);
+			}
+		}
+	}
+
+	fn labels<'a>(&'a self, labels: &mut Vec<(&'a str, i32)>) {
+		match self {
+			RollNode::Num(_) => {}
+			RollNode::Dice(roll) => if let Some(label) = &roll.label {
+				let value = roll.successes.unwrap_or(roll.total);
+				match labels.iter_mut().find(|(l, _)| l == label) {
+					Some((_, total)) => *total = total.saturating_add(value),
+					None => labels.push((label, value))
+				}
+			},
+			RollNode::Neg(inner) => inner.labels(labels),
+			RollNode::Op(_, lhs, rhs) => {
+				lhs.labels(labels);
+				rhs.labels(labels);
+			}
+		}
+	}
+
+	fn rolled_faces(&self, faces: &mut BTreeMap<i32, Vec<i32>>) {
+		match self {
+			RollNode::Num(_) => {}
+			RollNode::Dice(roll) => if let DieKind::Numeric(sides) = roll.kind {
+				let out = faces.entry(sides).or_default();
+				let all = roll.dice.iter().flat_map(|die| std::iter::once(die).chain(die.exploded.iter()));
+				for face in all.flat_map(|die| die.faces.iter()) {
+					out.push(face.value);
+					out.extend(face.unchosen.map(|side| roll.kind.value(side)));
+				}
+			},
+			RollNode::Neg(inner) => inner.rolled_faces(faces),
+			RollNode::Op(_, lhs, rhs) => {
+				lhs.rolled_faces(faces);
+				rhs.rolled_faces(faces);
+			}
+		}
+	}
+
+	//	Successes across every pool that counts them, if any do. A pool that's
+	//	subtracted or negated takes its successes away.
+	fn successes(&self) -> Option<i32> {
+		match self {
+			RollNode::Num(_) => None,
+			RollNode::Dice(roll) => roll.successes,
+			RollNode::Neg(inner) => inner.successes().map(|n| -n),
+			RollNode::Op(op, lhs, rhs) => {
+				let rhs = match op {
+					ArithOp::Sub => rhs.successes().map(|n| -n),
+					_ => rhs.successes()
+				};
+				match (lhs.successes(), rhs) {
+					(Some(l), Some(r)) => Some(l + r),
+					(l, r) => l.or(r)
+				}
+			}
+		}
+	}
+}
+
+//	Rendered as Discord markdown, e.g. "2d6 [3, ~~1~~→5] + 4 = 12". Discarded values
+//	(rerolled faces, dropped dice, the loser of advantage) are struck through and
+//	exploding faces are marked with a '!'. Successes are bold and failures underlined.
+impl fmt::Display for RollResult {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} = {}", self.node, self.total)?;
+
+		let labels = self.labels();
+		if !labels.is_empty() {
+			let labels: Vec<String> = labels.iter().map(|(label, total)| format!("{} {}", total, label)).collect();
+			write!(f, " ({})", labels.join(", "))?;
+		}
+		match &self.comment {
+			Some(comment) => write!(f, " # {}", comment),
+			None => Ok(())
+		}
+	}
+}
+
+impl fmt::Display for RollNode {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			RollNode::Num(n) => write!(f, "{}", n),
+			RollNode::Dice(roll) => write!(f, "{}", roll),
+			RollNode::Neg(inner) => match **inner {
+				RollNode::Op(..) => write!(f, "-({})", inner),
+				_ => write!(f, "-{}", inner),
+			},
+			RollNode::Op(op, lhs, rhs) => {
+				//	Parenthesise children that bind looser than this node; on the right
+				//	that includes equal precedence, since "a-(b-c)" isn't "a-b-c".
+				let wrap = |child: &RollNode, right: bool| match child {
+					RollNode::Op(inner, ..) => inner.precedence() < op.precedence()
+						|| (right && inner.precedence() == op.precedence()),
+					_ => false
+				};
+
+				match wrap(lhs, false) {
+					true => write!(f, "({})", lhs)?,
+					false => write!(f, "{}", lhs)?,
+				}
+				write!(f, " {} ", op)?;
+				match wrap(rhs, true) {
+					true => write!(f, "({})", rhs),
+					false => write!(f, "{}", rhs),
+				}
+			}
+		}
+	}
+}
+
+impl fmt::Display for ArithOp {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ArithOp::Add => write!(f, "+"),
+			ArithOp::Sub => write!(f, "-"),
+			//	Escaped so Discord doesn't read it as italics
+			ArithOp::Mul => write!(f, "\\*"),
+			ArithOp::Div => write!(f, "/"),
+			ArithOp::DivCeil => write!(f, "/^"),
+		}
+	}
+}
+
+impl fmt::Display for DiceRoll {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let dice: Vec<String> = pool(&self.dice)
+			.map(|die| die.render(&self.kind))
+			.collect();
+
+		write!(f, "{}d{}", self.count, self.kind)?;
+		if let Some(label) = &self.label { write!(f, "[{}]", label)?; }
+		write!(f, " [{}]", dice.join(", "))?;
+		match self.successes {
+			Some(1) => write!(f, " (1 success)")?,
+			Some(n) => write!(f, " ({} successes)", n)?,
+			None => {}
+		}
+
+		//	Symbols don't add up to anything, so count them instead
+		let DieKind::Custom(sides) = &self.kind else { return Ok(()) };
+		let mut tally: Vec<(&str, i32)> = vec![];
+		for die in pool(&self.dice) {
+			let Some(Side::Symbol(symbol)) = sides.get(die.side()) else { continue };
+			if !die.kept { continue }
+
+			match tally.iter_mut().find(|(s, _)| s == symbol) {
+				Some((_, n)) => *n += 1,
+				None => tally.push((symbol, 1)),
+			}
+		}
+
+		if tally.is_empty() { return Ok(()) }
+		let tally: Vec<String> = tally.iter().map(|(s, n)| format!("{} ×{}", s, n)).collect();
+		write!(f, " ({})", tally.join(", "))
+	}
+}
+
+impl DieRoll {
+	fn side(&self) -> usize {
+		self.faces.last().map(|face| face.side).unwrap_or(0)
+	}
+
+	fn render(&self, kind: &DieKind) -> String {
+		if !self.kept {
+			return format!("~~{}~~", self.value())
+		}
+
+		//	Compounded dice show each roll that went into them, e.g. "6!+6!+2"
+		let mut out = self.render_faces(kind);
+		if self.compounded {
+			for extra in &self.exploded {
+				out += &format!("+{}", extra.render_faces(kind));
+			}
+		}
+
+		match self.success {
+			Some(true) => format!("**{}**", out),
+			Some(false) => format!("__{}__", out),
+			None => out,
+		}
+	}
+
+	//	The reroll chain of this die alone.
+	fn render_faces(&self, kind: &DieKind) -> String {
+		let Some((last, rerolled)) = self.faces.split_last() else { return String::new() };
+
+		let mut out = String::new();
+		for face in rerolled {
+			out += &format!("~~{}~~→", face_name(kind, face.side));
+		}
+
+		//	Penetrating dice are shown at what they're worth
+		out += &match self.penalty {
+			0 => face_name(kind, last.side),
+			penalty => last.value.saturating_sub(penalty).to_string(),
+		};
+		if self.exploding { out += "!"; }
+		if let Some(unchosen) = last.unchosen {
+			out += &format!("|~~{}~~", face_name(kind, unchosen));
+		}
+
+		out
+	}
+}
+
+//	How a face is written in a breakdown - Fate dice get their usual +/-/blank.
+fn face_name(kind: &DieKind, side: usize) -> String {
+	match kind {
+		DieKind::Fate => ["-", "0", "+"].get(side).unwrap_or(&"0").to_string(),
+		DieKind::Custom(sides) => sides.get(side).map(|s| s.to_string()).unwrap_or_default(),
+		DieKind::Numeric(_) => kind.value(side).to_string(),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::{SeedableRng, rngs::StdRng};
+
+	use super::*;
+	use super::super::{DEFAULT_MAX_EXPLOSIONS, stats::StatsError};
+
+	fn die(s: &str) -> Dice {
+		match s.parse::<DiceCommand>().unwrap().expr {
+			Expr::Dice(die) => die,
+			other => panic!("Expected dice, got {:?}", other),
+		}
+	}
+
+	fn blank() -> DieRoll {
+		DieRoll{
+			faces: vec![],
+			exploded: vec![],
+			exploding: false,
+			compounded: false,
+			penalty: 0,
+			kept: true,
+			subtotal: 0,
+			success: None
+		}
+	}
+
+	fn face(value: i32) -> Face {
+		Face{ side: value as usize - 1, value, unchosen: None }
+	}
+
+	#[test]
+	fn division_rounding() {
+		assert_eq!(ArithOp::Div.apply(7, 2), Ok(3));
+		assert_eq!(ArithOp::DivCeil.apply(7, 2), Ok(4));
+		assert_eq!(ArithOp::Div.apply(-7, 2), Ok(-4));
+		assert_eq!(ArithOp::DivCeil.apply(-7, 2), Ok(-3));
+		assert_eq!(ArithOp::Div.apply(6, 0), Err(RollError::DivideByZero));
+	}
+
+	#[test]
+	fn keep_drop() {
+		let rolls = [3, 6, 1, 4];
+		assert_eq!(die("4d6kh3").kept(&rolls), vec![true, true, false, true]);
+		assert_eq!(die("4d6kl1").kept(&rolls), vec![false, false, true, false]);
+		assert_eq!(die("4d6dl1").kept(&rolls), vec![true, true, false, true]);
+		assert_eq!(die("4d6dh3").kept(&rolls), vec![false, false, true, false]);
+		assert_eq!(die("4d6kh9").kept(&rolls), vec![true; 4]);
+		assert_eq!(die("4d6dl9").kept(&rolls), vec![false; 4]);
+
+		for seed in 0..100 {
+			let stat = die("4d6kh3").roll(&mut StdRng::seed_from_u64(seed), DEFAULT_MAX_EXPLOSIONS).unwrap();
+			assert!((3..=18).contains(&stat.total));
+			assert_eq!(stat.dice.iter().filter(|d| d.kept).count(), 3);
+		}
+	}
+
+	#[test]
+	fn breakdown() {
+		for seed in 0..100 {
+			let roll = die("3d6r1x6").roll(&mut StdRng::seed_from_u64(seed), DEFAULT_MAX_EXPLOSIONS).unwrap();
+
+			//	Subtotals run across the whole pool, explosions included
+			let pool: Vec<&DieRoll> = roll.dice.iter()
+				.flat_map(|die| std::iter::once(die).chain(die.exploded.iter()))
+				.collect();
+			assert_eq!(pool.last().unwrap().subtotal, roll.total);
+
+			for die in pool {
+				assert!(die.faces.len() == 1 || die.faces[0].value == 1);
+				assert!(die.exploded.iter().all(|extra| extra.exploded.is_empty()));
+			}
+		}
+	}
+
+	#[test]
+	fn seeded() {
+		let command: DiceCommand = "4d6kh3 + 2d20a - 1d8r1x8".parse().unwrap();
+		for seed in 0..20 {
+			let first = command.roll(&mut StdRng::seed_from_u64(seed)).unwrap();
+			let second = command.roll(&mut StdRng::seed_from_u64(seed)).unwrap();
+			assert_eq!(first, second);
+		}
+	}
+
+	#[test]
+	fn reroll() {
+		let mut rerolled = 0;
+		for seed in 0..200 {
+			let roll = die("1d6r1..2").roll(&mut StdRng::seed_from_u64(seed), DEFAULT_MAX_EXPLOSIONS).unwrap();
+			let faces = &roll.dice[0].faces;
+
+			//	Only faces in range get rerolled, and only once
+			match faces.as_slice() {
+				[first] => assert!(first.value > 2),
+				[first, _] => { assert!(first.value <= 2); rerolled += 1; }
+				other => panic!("Rerolled too often: {:?}", other),
+			}
+		}
+		assert!(rerolled > 0);
+	}
+
+	#[test]
+	fn explode() {
+		let mut exploded = 0;
+		for seed in 0..200 {
+			let roll = die("1d6x6").roll(&mut StdRng::seed_from_u64(seed), DEFAULT_MAX_EXPLOSIONS).unwrap();
+			let die = &roll.dice[0];
+
+			//	Every die but the last in the chain rolled a 6
+			let chain: Vec<i32> = std::iter::once(die).chain(die.exploded.iter()).map(|d| d.value()).collect();
+			let (last, exploding) = chain.split_last().unwrap();
+			assert!(exploding.iter().all(|v| *v == 6));
+			assert_ne!(*last, 6);
+			assert_eq!(roll.total, chain.iter().sum::<i32>());
+
+			exploded += exploding.len();
+		}
+		assert!(exploded > 0);
+	}
+
+	#[test]
+	fn compound_penetrate() {
+		for seed in 0..200 {
+			let roll = die("2d6!!").roll(&mut StdRng::seed_from_u64(seed), DEFAULT_MAX_EXPLOSIONS).unwrap();
+			assert_eq!(pool(&roll.dice).count(), 2);
+			for die in &roll.dice {
+				let chain: i32 = die.face() + die.exploded.iter().map(|d| d.face()).sum::<i32>();
+				assert_eq!(die.value(), chain);
+			}
+
+			let roll = die("1d6!p").roll(&mut StdRng::seed_from_u64(seed), DEFAULT_MAX_EXPLOSIONS).unwrap();
+			assert_eq!(pool(&roll.dice).count(), roll.dice[0].exploded.len() + 1);
+			for extra in &roll.dice[0].exploded {
+				assert_eq!(extra.value(), extra.face() - 1);
+			}
+		}
+	}
+
+	#[test]
+	fn explosion_cap() {
+		//	Would never stop without the cap
+		let roll = die("1d1!").roll(&mut rand::thread_rng(), 10).unwrap();
+		assert_eq!(roll.dice[0].exploded.len(), 10);
+		assert_eq!(roll.total, 11);
+
+		let command: DiceCommand = "1d1!!".parse().unwrap();
+		let roll = command.roll(&mut rand::thread_rng()).unwrap();
+		assert_eq!(roll.total, DEFAULT_MAX_EXPLOSIONS as i32 + 1);
+	}
+
+	#[test]
+	fn advantage() {
+		for seed in 0..200 {
+			let adv = die("1d20a").roll(&mut StdRng::seed_from_u64(seed), DEFAULT_MAX_EXPLOSIONS).unwrap();
+			let face = adv.dice[0].faces[0];
+			assert!(face.value >= adv.kind.value(face.unchosen.unwrap()));
+
+			let dis = die("1d20d").roll(&mut StdRng::seed_from_u64(seed), DEFAULT_MAX_EXPLOSIONS).unwrap();
+			let face = dis.dice[0].faces[0];
+			assert!(face.value <= dis.kind.value(face.unchosen.unwrap()));
+		}
+	}
+
+	#[test]
+	fn successes() {
+		for seed in 0..100 {
+			let command: DiceCommand = "10d10>=8f1 + 1".parse().unwrap();
+			let roll = command.roll(&mut StdRng::seed_from_u64(seed)).unwrap();
+
+			let RollNode::Op(_, ref pool, _) = roll.node else { panic!("Expected addition") };
+			let RollNode::Dice(ref pool) = **pool else { panic!("Expected dice") };
+			let expected: i32 = pool.dice.iter()
+				.map(|d| match d.value() { 8..=10 => 1, 1 => -1, _ => 0 })
+				.sum();
+
+			assert_eq!(pool.successes, Some(expected));
+			assert_eq!(roll.successes, Some(expected));
+			assert_eq!(roll.total, expected + 1);
+		}
+
+		//	Explosions add dice that can succeed too
+		for seed in 0..100 {
+			let pool = die("5d10>=8!").roll(&mut StdRng::seed_from_u64(seed), DEFAULT_MAX_EXPLOSIONS).unwrap();
+			let all: Vec<&DieRoll> = pool.dice.iter()
+				.flat_map(|die| std::iter::once(die).chain(die.exploded.iter()))
+				.collect();
+			assert_eq!(pool.successes, Some(all.iter().filter(|d| d.value() >= 8).count() as i32));
+		}
+
+		//	Subtracted pools take their successes away
+		for seed in 0..100 {
+			let command: DiceCommand = "4d6>4 - 2d6>4".parse().unwrap();
+			let roll = command.roll(&mut StdRng::seed_from_u64(seed)).unwrap();
+
+			let RollNode::Op(ArithOp::Sub, ref lhs, ref rhs) = roll.node else { panic!("Expected subtraction") };
+			let (lhs, rhs) = (lhs.successes().unwrap(), rhs.successes().unwrap());
+			assert_eq!(roll.successes, Some(lhs - rhs));
+			assert_eq!(roll.total, lhs - rhs);
+		}
+
+		let command: DiceCommand = "-(3d6>0)".parse().unwrap();
+		assert_eq!(command.roll(&mut StdRng::seed_from_u64(0)).unwrap().successes, Some(-3));
+	}
+
+	#[test]
+	fn kinds() {
+		for seed in 0..100 {
+			let fate = die("4dF").roll(&mut StdRng::seed_from_u64(seed), DEFAULT_MAX_EXPLOSIONS).unwrap();
+			assert!((-4..=4).contains(&fate.total));
+			assert!(fate.dice.iter().all(|d| (-1..=1).contains(&d.value())));
+
+			let fib = die("1d{1,1,2,3,5,8}").roll(&mut StdRng::seed_from_u64(seed), DEFAULT_MAX_EXPLOSIONS).unwrap();
+			assert!([1, 2, 3, 5, 8].contains(&fib.total));
+
+			let pct = die("1d%").roll(&mut StdRng::seed_from_u64(seed), DEFAULT_MAX_EXPLOSIONS).unwrap();
+			assert!((1..=100).contains(&pct.total));
+		}
+
+		let mut rng = StdRng::seed_from_u64(0);
+		let symbols = die("3d{hit}").roll(&mut rng, DEFAULT_MAX_EXPLOSIONS).unwrap();
+		assert_eq!(symbols.total, 0);
+		assert_eq!(symbols.to_string(), "3d{hit} [hit, hit, hit] (hit ×3)");
+	}
+
+	#[test]
+	fn render() {
+		let roll = RollResult{
+			total: 12,
+			successes: None,
+			node: RollNode::Op(
+				ArithOp::Add,
+				Box::new(RollNode::Dice(DiceRoll{
+					count: 2,
+					kind: DieKind::Numeric(6),
+					label: None,
+					dice: vec![
+						DieRoll{ faces: vec![face(3)], ..blank() },
+						DieRoll{ faces: vec![face(1), face(5)], ..blank() },
+					],
+					total: 8,
+					successes: None
+				})),
+				Box::new(RollNode::Num(4))
+			),
+			comment: None
+		};
+		assert_eq!(roll.to_string(), "2d6 [3, ~~1~~→5] + 4 = 12");
+
+		let compounded = DiceRoll{
+			count: 1,
+			kind: DieKind::Numeric(6),
+			label: None,
+			dice: vec![DieRoll{
+				faces: vec![face(6)],
+				exploded: vec![
+					DieRoll{ faces: vec![face(6)], exploding: true, ..blank() },
+					DieRoll{ faces: vec![face(2)], ..blank() },
+				],
+				exploding: true,
+				compounded: true,
+				..blank()
+			}],
+			total: 14,
+			successes: None
+		};
+		assert_eq!(compounded.to_string(), "1d6 [6!+6!+2]");
+	}
+
+	#[test]
+	fn labels() {
+		for seed in 0..50 {
+			let command: DiceCommand = "2d6[slashing] + 1d6[fire] + 1d4[slashing] + 3 # greatsword".parse().unwrap();
+			let roll = command.roll(&mut StdRng::seed_from_u64(seed)).unwrap();
+
+			let labels = roll.labels();
+			assert_eq!(labels.iter().map(|(label, _)| *label).collect::<Vec<_>>(), ["slashing", "fire"]);
+			assert_eq!(labels.iter().map(|(_, total)| total).sum::<i32>() + 3, roll.total);
+
+			let shown = roll.to_string();
+			assert!(shown.starts_with("2d6[slashing] ["), "{}", shown);
+			assert!(shown.ends_with(&format!(
+				"= {} ({} slashing, {} fire) # greatsword", roll.total, labels[0].1, labels[1].1
+			)), "{}", shown);
+		}
+
+		let plain = "1d20 + 5".parse::<DiceCommand>().unwrap().roll(&mut StdRng::seed_from_u64(0)).unwrap();
+		assert!(plain.labels().is_empty());
+		assert!(!plain.to_string().contains('('));
+	}
+
+	#[test]
+	fn arithmetic() {
+		let command: DiceCommand = "(2 + 3) * 4 - 10 / 3".parse().unwrap();
+		let roll = command.roll(&mut rand::thread_rng()).unwrap();
+		assert_eq!(roll.total, 17);
+		assert_eq!(roll.to_string(), "(2 + 3) \\* 4 - 10 / 3 = 17");
+	}
+
+	#[test]
+	fn overflow() {
+		let mut rng = rand::thread_rng();
+		for expr in ["2147483647 + 1", "-2147483647 - 2", "65536 * 65536", "1d{2147483647} + 1d{2147483647}", "2d{2147483647}"] {
+			let command: DiceCommand = expr.parse().unwrap();
+			assert_eq!(command.roll(&mut rng), Err(RollError::Overflow), "{}", expr);
+		}
+	}
+
+	#[test]
+	fn extreme_faces() {
+		//	Faces at the very ends of an i32 roll, render & work out odds without panicking
+		let mut rng = StdRng::seed_from_u64(0);
+		for expr in ["2d{-2147483648}kh1", "2d{2147483647,-2147483648}kl1", "1d{-2147483648}!p", "1d{2147483647}!", "1d{2147483647}!!"] {
+			let command: DiceCommand = expr.parse().unwrap();
+			let _ = command.roll(&mut rng).map(|roll| roll.to_string());
+			let _ = command.distribution();
+		}
+
+		let command: DiceCommand = "2d{-2147483648}kh1".parse().unwrap();
+		assert_eq!(command.roll(&mut rng).unwrap().total, i32::MIN);
+		let command: DiceCommand = "1d{2147483647}!".parse().unwrap();
+		assert_eq!(command.distribution(), Err(StatsError::Overflow));
+
+		//	Odds for a roll that can only overflow are an overflow too
+		let command: DiceCommand = "-(2d{-2147483648}kh1)".parse().unwrap();
+		assert_eq!(command.roll(&mut rng), Err(RollError::Overflow));
+		assert_eq!(command.distribution(), Err(StatsError::Overflow));
+	}
+}
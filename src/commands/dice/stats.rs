@@ -0,0 +1,469 @@
+//		Imports
+use std::{
+	fmt,
+	collections::BTreeMap,
+};
+
+use super::{
+	DiceCommand, Expr, Dice, DiceArg, ArithOp,
+	roll::RollError
+};
+
+//		Data
+//	The distribution stops following explosions after this many extra dice, even if the
+//	roll itself would allow more. Whatever probability is left over is negligible for any
+//	sane range.
+const EXPLOSION_DEPTH: u32 = 20;
+
+//	Upper bound on the steps one stats request can take, across every combination,
+//	explosion and keep it works through. It runs to well under a second, and anything
+//	bigger is refused rather than left to tie up the bot.
+const MAX_WORK: usize = 5_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StatsError {
+	DivideByZero,
+	Overflow,
+	Unresolved(String),
+	TooComplex,
+	ExplodingKeep,
+}
+
+impl fmt::Display for StatsError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::DivideByZero => write!(f, "That roll can divide by zero"),
+			Self::Overflow => write!(f, "That roll can come out too big to count"),
+			Self::Unresolved(name) => write!(f, "{} has to be filled in first", name),
+			Self::TooComplex => write!(f, "That roll has too many outcomes to work out exactly"),
+			Self::ExplodingKeep => write!(f, "Can't work out the odds of keeping or dropping exploding dice"),
+		}
+	}
+}
+
+//	Exact probability of every outcome of a roll.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution {
+	outcomes:BTreeMap<i32, f64>
+}
+
+//	Steps left before a request gives up as too complex.
+struct Budget(usize);
+
+//		Functions
+impl DiceCommand {
+	pub fn distribution(&self) -> Result<Distribution, StatsError> {
+		self.expr.distribution(self.max_explosions.min(EXPLOSION_DEPTH), &mut Budget(MAX_WORK))
+	}
+}
+
+impl Budget {
+	fn spend(&mut self, work: usize) -> Result<(), StatsError> {
+		self.0 = self.0.checked_sub(work).ok_or(StatsError::TooComplex)?;
+		Ok(())
+	}
+}
+
+impl Expr {
+	fn distribution(&self, depth: u32, budget: &mut Budget) -> Result<Distribution, StatsError> {
+		match self {
+			Expr::Num(n) => Ok(Distribution::constant(*n)),
+			Expr::Dice(die) => die.distribution(depth, budget),
+			Expr::Macro(name) => Err(StatsError::Unresolved(format!("${}", name))),
+			Expr::Var(name) => Err(StatsError::Unresolved(format!("@{}", name))),
+			Expr::Neg(inner) => {
+				//	The one value with no negative, which a roll reports as overflowing
+				let inner = inner.distribution(depth, budget)?;
+				if inner.outcomes.contains_key(&i32::MIN) { return Err(StatsError::Overflow) }
+				Ok(inner.map(|v| -v))
+			}
+			Expr::Op(op, lhs, rhs) => {
+				let (lhs, rhs) = (lhs.distribution(depth, budget)?, rhs.distribution(depth, budget)?);
+				lhs.combine(&rhs, budget, |l, r| op.apply(l, r))
+			}
+		}
+	}
+}
+
+impl Dice {
+	fn distribution(&self, depth: u32, budget: &mut Budget) -> Result<Distribution, StatsError> {
+		//	Success pools add up +1/-1 per die instead of the faces themselves
+		let counting = self.args.iter().any(|a| matches!(a, DiceArg::Success(..) | DiceArg::Failure(..)));
+		let score = |v: i32| match (counting, self.success(v)) {
+			(false, _) => v,
+			(true, Some(true)) => 1,
+			(true, Some(false)) => -1,
+			(true, None) => 0,
+		};
+
+		let subset = self.args.iter().find_map(|a| match a {
+			DiceArg::Keep(highest, n) => Some((*highest, *n)),
+			DiceArg::Drop(highest, n) => Some((!*highest, self.count - n)),
+			_ => None
+		});
+
+		match subset {
+			//	Compounded dice stay one die, but anything else grows the pool
+			Some(_) if self.args.iter().any(|a| matches!(a, DiceArg::Extra(..) | DiceArg::Penetrate(..))) => {
+				Err(StatsError::ExplodingKeep)
+			}
+			Some((highest, n)) => {
+				//	Keep/drop has to compare the faces themselves, so it does its own scoring
+				let die = self.die_distribution(&|v| v, depth, budget)?;
+				keep_distribution(&die, self.count, n.clamp(0, self.count), highest, &score, budget)
+			}
+			None => {
+				let die = self.die_distribution(&score, depth, budget)?;
+				let mut sum = Distribution::constant(0);
+				for _ in 0..self.count {
+					sum = sum.combine(&die, budget, |l, r| ArithOp::Add.apply(l, r))?;
+				}
+
+				Ok(sum)
+			}
+		}
+	}
+
+	//	One face of the die, with advantage applied.
+	fn face_distribution(&self, budget: &mut Budget) -> Result<Distribution, StatsError> {
+		let faces = self.kind.faces();
+		let mut face = Distribution{ outcomes: BTreeMap::new() };
+		for side in 0..faces {
+			face.add(self.kind.value(side), 1.0 / faces as f64);
+		}
+
+		//	Advantage is the better of two independent faces
+		let advantage = self.args.iter().find_map(|a| match a {
+			DiceArg::Advantage(p) => Some(*p),
+			_ => None
+		});
+		match advantage {
+			Some(p) => face.combine(&face, budget, |l, r| Ok(if p { l.max(r) } else { l.min(r) })),
+			None => Ok(face)
+		}
+	}
+
+	//	One die of the pool, including its reroll and any dice it explodes into. Each
+	//	die in an explosion chain is scored as it's added, except for compounding where
+	//	the whole chain is one die.
+	fn die_distribution(
+		&self,
+		score: &dyn Fn(i32) -> i32,
+		depth: u32,
+		budget: &mut Budget
+	) -> Result<Distribution, StatsError> {
+		let face = self.face_distribution(budget)?;
+		let rerolls = |v: i32| self.args.iter().any(|a|
+			matches!(a, DiceArg::Reroll(l, r) if (*l..=*r).contains(&v)));
+
+		//	A face in the reroll range is replaced by a fresh face, once
+		let reroll_chance: f64 = face.outcomes.iter()
+			.filter(|(v, _)| rerolls(**v))
+			.map(|(_, p)| p)
+			.sum();
+		let rerolled = Distribution{
+			outcomes: face.outcomes.iter()
+				.map(|(v, p)| {
+					let kept = if rerolls(*v) { 0.0 } else { *p };
+					(*v, kept + reroll_chance * p)
+				})
+				.collect()
+		};
+
+		let compound = self.args.iter().any(|a| matches!(a, DiceArg::Compound(..)));
+		let penalty = if self.args.iter().any(|a| matches!(a, DiceArg::Penetrate(..))) { 1 } else { 0 };
+		let worth = |v: i32, penalty: i32| match compound {
+			true => v.saturating_sub(penalty),
+			false => score(v.saturating_sub(penalty))
+		};
+
+		//	Unroll explosions from the deepest extra die back up to the first, which is
+		//	the only one that escapes the penetration penalty
+		let mut chain = rerolled.map(|v| worth(v, if depth == 0 { 0 } else { penalty }));
+		for level in 1..=depth {
+			let penalty = if level == depth { 0 } else { penalty };
+			budget.spend(rerolled.outcomes.len().saturating_mul(chain.outcomes.len()))?;
+
+			let mut next = Distribution{ outcomes: BTreeMap::new() };
+			for (v, p) in &rerolled.outcomes {
+				if !self.explodes(*v) {
+					next.add(worth(*v, penalty), *p);
+					continue;
+				}
+
+				for (rest, q) in &chain.outcomes {
+					let value = worth(*v, penalty).checked_add(*rest).ok_or(StatsError::Overflow)?;
+					next.add(value, p * q);
+				}
+			}
+			chain = next;
+		}
+
+		Ok(if compound { chain.map(score) } else { chain })
+	}
+}
+
+//	Sum of the highest (or lowest) `keep` of `count` dice. Walks the faces from the best
+//	downwards, choosing how many dice land on each face; the first `keep` dice placed are
+//	the ones that count. Each path through that carries its multinomial weight.
+fn keep_distribution(
+	die: &Distribution,
+	count: i32,
+	keep: i32,
+	highest: bool,
+	score: &dyn Fn(i32) -> i32,
+	budget: &mut Budget
+) -> Result<Distribution, StatsError> {
+	let count = count as usize;
+	let keep = keep as usize;
+
+	let mut faces: Vec<(i32, f64)> = die.outcomes.iter().map(|(v, p)| (*v, *p)).collect();
+	if highest { faces.reverse(); }
+
+	//	(dice placed, kept sum) -> probability
+	let mut states: BTreeMap<(usize, i32), f64> = BTreeMap::new();
+	states.insert((0, 0), 1.0);
+
+	for (value, p) in faces {
+		let mut next: BTreeMap<(usize, i32), f64> = BTreeMap::new();
+		for ((placed, sum), weight) in states {
+			let remaining = count - placed;
+			budget.spend(remaining + 1)?;
+			let mut ways = 1.0;
+			for j in 0..=remaining {
+				let kept = j.min(keep.saturating_sub(placed)) as i32;
+				let sum = kept.checked_mul(score(value))
+					.and_then(|worth| sum.checked_add(worth))
+					.ok_or(StatsError::Overflow)?;
+				*next.entry((placed + j, sum)).or_insert(0.0) += weight * ways * p.powi(j as i32);
+
+				//	C(remaining, j + 1) from C(remaining, j)
+				ways = ways * (remaining - j) as f64 / (j + 1) as f64;
+			}
+		}
+		states = next;
+	}
+
+	let mut out = Distribution{ outcomes: BTreeMap::new() };
+	for ((placed, sum), p) in states {
+		if placed == count { out.add(sum, p); }
+	}
+
+	Ok(out)
+}
+
+impl Distribution {
+	fn constant(n: i32) -> Self {
+		Distribution{ outcomes: BTreeMap::from([(n, 1.0)]) }
+	}
+
+	fn add(&mut self, value: i32, p: f64) {
+		if p > 0.0 { *self.outcomes.entry(value).or_insert(0.0) += p; }
+	}
+
+	fn map(&self, f: impl Fn(i32) -> i32) -> Self {
+		let mut out = Distribution{ outcomes: BTreeMap::new() };
+		for (v, p) in &self.outcomes { out.add(f(*v), *p); }
+
+		out
+	}
+
+	//	Distribution of f(a, b) for independent a & b.
+	fn combine(
+		&self,
+		other: &Distribution,
+		budget: &mut Budget,
+		f: impl Fn(i32, i32) -> Result<i32, RollError>
+	) -> Result<Self, StatsError> {
+		budget.spend(self.outcomes.len().saturating_mul(other.outcomes.len()))?;
+
+		let mut out = Distribution{ outcomes: BTreeMap::new() };
+		for (l, p) in &self.outcomes {
+			for (r, q) in &other.outcomes {
+				let value = f(*l, *r).map_err(|e| match e {
+					RollError::DivideByZero => StatsError::DivideByZero,
+					RollError::Overflow => StatsError::Overflow,
+					RollError::Unresolved(name) => StatsError::Unresolved(name),
+				})?;
+				out.add(value, p * q);
+			}
+		}
+
+		Ok(out)
+	}
+
+	pub fn min(&self) -> i32 {
+		self.outcomes.keys().next().copied().unwrap_or(0)
+	}
+
+	pub fn max(&self) -> i32 {
+		self.outcomes.keys().next_back().copied().unwrap_or(0)
+	}
+
+	pub fn mean(&self) -> f64 {
+		self.outcomes.iter().map(|(v, p)| *v as f64 * p).sum()
+	}
+
+	pub fn stdev(&self) -> f64 {
+		let mean = self.mean();
+		self.outcomes.iter()
+			.map(|(v, p)| (*v as f64 - mean).powi(2) * p)
+			.sum::<f64>()
+			.sqrt()
+	}
+
+	//	Smallest outcome with at least `q` of the probability at or below it.
+	pub fn percentile(&self, q: f64) -> i32 {
+		let mut cumulative = 0.0;
+		for (v, p) in &self.outcomes {
+			cumulative += p;
+			if cumulative >= q - 1e-9 { return *v }
+		}
+
+		self.max()
+	}
+
+	pub fn at_least(&self, n: i32) -> f64 {
+		self.outcomes.range(n..).map(|(_, p)| p).sum()
+	}
+}
+
+impl fmt::Display for Distribution {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		writeln!(f, "min {} · max {} · mean {:.2} · stdev {:.2}",
+			self.min(), self.max(), self.mean(), self.stdev())?;
+
+		let percentiles: Vec<String> = [10, 25, 50, 75, 90].iter()
+			.map(|q| format!("{}% {}", q, self.percentile(*q as f64 / 100.0)))
+			.collect();
+		write!(f, "percentiles: {}", percentiles.join(" · "))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn dist(s: &str) -> Distribution {
+		s.parse::<DiceCommand>().unwrap().distribution().unwrap()
+	}
+
+	fn close(a: f64, b: f64) -> bool {
+		(a - b).abs() < 1e-6
+	}
+
+	#[test]
+	fn sums() {
+		let d = dist("2d6");
+		assert_eq!((d.min(), d.max()), (2, 12));
+		assert!(close(d.outcomes[&7], 6.0 / 36.0));
+		assert!(close(d.mean(), 7.0));
+		assert_eq!(d.percentile(0.5), 7);
+
+		let d = dist("1d20+5");
+		assert!(close(d.at_least(15), 0.55));
+	}
+
+	#[test]
+	fn advantage_reroll() {
+		assert!(close(dist("1d20a").mean(), 13.825));
+		assert!(close(dist("1d20d").mean(), 7.175));
+		assert!(close(dist("1d6r1").mean(), 3.5 / 6.0 + 20.0 / 6.0));
+	}
+
+	#[test]
+	fn keep_drop() {
+		//	Brute force over every 4d6 roll
+		let mut total = 0.0;
+		for a in 1..=6 { for b in 1..=6 { for c in 1..=6 { for d in 1..=6 {
+			let mut dice = [a, b, c, d];
+			dice.sort();
+			total += (dice[1] + dice[2] + dice[3]) as f64;
+		}}}}
+
+		assert!(close(dist("4d6kh3").mean(), total / 1296.0));
+		assert!(close(dist("4d6dl1").mean(), total / 1296.0));
+		assert!(close(dist("2d20kl1").mean(), dist("1d20d").mean()));
+		assert!(close(dist("4d6kh3").outcomes.values().sum::<f64>(), 1.0));
+	}
+
+	#[test]
+	fn explode() {
+		//	E[X] = 3.5 + E[X]/6
+		assert!(close(dist("1d6x6").mean(), 4.2));
+
+		let err = "4d6kh3x6".parse::<DiceCommand>().unwrap().distribution();
+		assert_eq!(err, Err(StatsError::ExplodingKeep));
+
+		//	Compounding gives the same sums as adding extra dice
+		assert!(close(dist("1d6!!").mean(), 4.2));
+		assert_eq!(dist("2d6!!kh1").min(), 1);
+
+		//	Penetrating dice are worth one less: E[X] = 3.5 + (E[X] - 1)/6
+		assert!(close(dist("1d6!p").mean(), 4.0));
+		assert!(!dist("1d6!").outcomes.contains_key(&12));
+		assert!(dist("1d6!p").outcomes.contains_key(&12));
+
+		//	The cap is exact when it's small enough to follow
+		let capped = "1d6!".parse::<DiceCommand>().unwrap().with_max_explosions(1);
+		let d = capped.distribution().unwrap();
+		assert_eq!(d.max(), 12);
+		assert!(close(d.outcomes[&12], 1.0 / 36.0));
+	}
+
+	#[test]
+	fn successes() {
+		//	Each d10 succeeds on 8+ with probability 0.3
+		let d = dist("10d10>=8");
+		assert!(close(d.mean(), 3.0));
+		assert!(close(d.outcomes[&0], 0.7f64.powi(10)));
+
+		let d = dist("10d10>=8f1");
+		assert!(close(d.mean(), 2.0));
+		assert_eq!((d.min(), d.max()), (-10, 10));
+
+		//	Brute force: successes less failures among the three kept dice
+		let mut total = 0.0;
+		for a in 1..=6 { for b in 1..=6 { for c in 1..=6 { for d in 1..=6 {
+			let mut dice = [a, b, c, d];
+			dice.sort();
+			total += dice[1..].iter().map(|v| match v { 5..=6 => 1.0, 2 => -1.0, _ => 0.0 }).sum::<f64>();
+		}}}}
+		assert!(close(dist("4d6kh3>=5f2").mean(), total / 1296.0));
+	}
+
+	#[test]
+	fn kinds() {
+		let d = dist("4dF");
+		assert_eq!((d.min(), d.max()), (-4, 4));
+		assert!(close(d.mean(), 0.0));
+		assert!(close(d.outcomes[&4], 1.0 / 81.0));
+
+		assert!(close(dist("1d{1,1,2,3,5,8}").mean(), 20.0 / 6.0));
+		assert!(close(dist("d%").mean(), 50.5));
+		assert!(close(dist("2d{hit,miss}").mean(), 0.0));
+	}
+
+	#[test]
+	fn arithmetic() {
+		let d = dist("(1d4+1)*2");
+		assert_eq!(d.outcomes.keys().copied().collect::<Vec<_>>(), vec![4, 6, 8, 10]);
+		assert_eq!(dist("1d8/2").max(), 4);
+
+		let err = "1d6/(1d2-1)".parse::<DiceCommand>().unwrap().distribution();
+		assert_eq!(err, Err(StatsError::DivideByZero));
+	}
+
+	#[test]
+	fn budget() {
+		//	Well within the roll limits, but far too much work to do exactly
+		for roll in ["60d100kh30", "1000d1000", "1d10000a", "200d100!"] {
+			let err = roll.parse::<DiceCommand>().unwrap().distribution();
+			assert_eq!(err, Err(StatsError::TooComplex), "{}", roll);
+		}
+
+		//	Sizeable, but still fine
+		assert!(close(dist("20d20kh10").outcomes.values().sum::<f64>(), 1.0));
+		assert!(close(dist("50d20").mean(), 525.0));
+	}
+}
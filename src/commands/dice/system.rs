@@ -0,0 +1,261 @@
+//		Imports
+use std::{fmt, str::FromStr};
+use serde::{Deserialize, Serialize};
+
+use twilight_model::{
+	gateway::payload::incoming::MessageCreate,
+	id::{Id, marker::ChannelMarker}
+};
+
+use crate::{
+	BotResult,
+	InteractionContext,
+	data::{ChannelData, GuildData}
+};
+
+use super::{
+	history, is_manager,
+	roll::RollResult
+};
+
+//		Data
+//	A game whose rules say what a roll means, beyond its number. Set for a whole guild,
+//	or for one channel when a server runs more than one game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameSystem {
+	//	Natural 20s & 1s on a d20
+	Dnd5e,
+	//	2d6 bands: 6-, 7-9, 10+
+	Pbta,
+	//	The best of a d6 pool, critical on two sixes
+	Blades,
+	//	Special rolls of 1 & 17-20 on a d20
+	Cypher,
+	//	d100 under a skill, with hard & extreme successes
+	Cthulhu,
+}
+
+const SYSTEMS: [GameSystem; 5] = [
+	GameSystem::Dnd5e, GameSystem::Pbta, GameSystem::Blades, GameSystem::Cypher, GameSystem::Cthulhu
+];
+
+impl GameSystem {
+	fn name(self) -> &'static str {
+		match self {
+			GameSystem::Dnd5e => "5e",
+			GameSystem::Pbta => "pbta",
+			GameSystem::Blades => "blades",
+			GameSystem::Cypher => "cypher",
+			GameSystem::Cthulhu => "coc",
+		}
+	}
+}
+
+impl FromStr for GameSystem {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.to_ascii_lowercase();
+		SYSTEMS.into_iter()
+			.find(|system| system.name() == s)
+			.ok_or_else(|| format!("unknown system `{}`; try {}", s, names()))
+	}
+}
+
+impl fmt::Display for GameSystem {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.name())
+	}
+}
+
+fn names() -> String {
+	let names: Vec<&str> = SYSTEMS.iter().map(|system| system.name()).collect();
+	names.join(", ")
+}
+
+//		Functions
+impl GameSystem {
+	//	What a roll means under this system's rules, if it's the kind of roll the system
+	//	has rules for. `skill` is the target for systems that roll under one.
+	pub fn interpret(self, roll: &RollResult, skill: Option<i32>) -> Option<String> {
+		let faces = match self {
+			GameSystem::Dnd5e | GameSystem::Cypher => roll.kept_faces(20),
+			GameSystem::Pbta | GameSystem::Blades => roll.kept_faces(6),
+			GameSystem::Cthulhu => roll.kept_faces(100),
+		};
+
+		self.outcome(roll.total, &faces, skill).map(str::to_owned)
+	}
+
+	fn outcome(self, total: i32, faces: &[i32], skill: Option<i32>) -> Option<&'static str> {
+		match self {
+			GameSystem::Dnd5e => {
+				if faces.contains(&20) { Some("natural 20, critical hit!") }
+				else if faces.contains(&1) { Some("natural 1, critical miss") }
+				else { None }
+			}
+			GameSystem::Pbta => match (faces.len(), total) {
+				(2, ..=6) => Some("miss"),
+				(2, 7..=9) => Some("weak hit"),
+				(2, _) => Some("strong hit"),
+				_ => None
+			},
+			GameSystem::Blades => {
+				let sixes = faces.iter().filter(|&&face| face == 6).count();
+				match (sixes, faces.iter().max()) {
+					(_, None) => None,
+					(2.., _) => Some("critical success"),
+					(1, _) => Some("full success"),
+					(_, Some(4 | 5)) => Some("partial success"),
+					_ => Some("bad outcome")
+				}
+			}
+			GameSystem::Cypher => match faces {
+				[1] => Some("GM intrusion"),
+				[17] => Some("+1 damage"),
+				[18] => Some("+2 damage"),
+				[19] => Some("minor effect"),
+				[20] => Some("major effect"),
+				_ => None
+			},
+			GameSystem::Cthulhu => {
+				let &[face] = faces else { return None };
+				//	Below 50 a skill fumbles on 96 and up, otherwise only on 100
+				let fumble = match skill {
+					Some(skill) if skill < 50 => face >= 96,
+					_ => face == 100
+				};
+
+				match skill {
+					_ if face == 1 => Some("critical success"),
+					_ if fumble => Some("fumble"),
+					Some(skill) if face <= skill / 5 => Some("extreme success"),
+					Some(skill) if face <= skill / 2 => Some("hard success"),
+					Some(skill) if face <= skill => Some("regular success"),
+					Some(_) => Some("failure"),
+					None => None
+				}
+			}
+		}
+	}
+}
+
+//	The system a channel plays, which overrides the guild's.
+pub(super) async fn system_for(
+	channel_id: Option<Id<ChannelMarker>>,
+	guild_data: Option<&GuildData>
+) -> BotResult<Option<GameSystem>> {
+	let channel = match channel_id {
+		Some(channel_id) => history::load(channel_id).await?.system,
+		None => None
+	};
+
+	Ok(channel.or(guild_data.and_then(|data| data.system)))
+}
+
+//	"system" shows what's in use, "system <name|off> [--channel]" changes it for the
+//	guild or just this channel.
+pub(super) async fn system_reply(
+	ctx: &InteractionContext,
+	msg: &MessageCreate,
+	guild_data: Option<&mut GuildData>,
+	args: &str
+) -> BotResult<String> {
+	let words: Vec<&str> = args.split_whitespace().collect();
+	let (name, channel_only) = match words[..] {
+		[] => return Ok(match system_for(Some(msg.channel_id), guild_data.as_deref()).await? {
+			Some(system) => format!("rolls here are read as {}", system),
+			None => format!("no game system is set; choose one of {} with `!dice system <name>`", names())
+		}),
+		[name] => (name, guild_data.is_none()),
+		[name, "--channel"] => (name, true),
+		_ => return Ok("usage: system [<name>|off] [--channel]".to_string())
+	};
+
+	let system = match name {
+		"off" => None,
+		name => match name.parse::<GameSystem>() {
+			Ok(system) => Some(system),
+			Err(e) => return Ok(e)
+		}
+	};
+	if let Some(data) = &guild_data {
+		if !is_manager(ctx, msg, data).await? {
+			return Ok("only server managers can change the game system".to_string())
+		}
+	}
+
+	let shown = system.map(|system| system.to_string()).unwrap_or("plain numbers".to_string());
+	match (channel_only, guild_data) {
+		(false, Some(data)) => {
+			data.system = system;
+			data.write_file().await?;
+			Ok(format!("rolls in this server are now read as {}", shown))
+		}
+		_ => {
+			let _lock = ChannelData::lock(msg.channel_id).await;
+			let mut channel = history::load(msg.channel_id).await?;
+			channel.system = system;
+			channel.write_file().await?;
+			Ok(format!("rolls in this channel are now read as {}", shown))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rand::{SeedableRng, rngs::StdRng};
+	use crate::commands::dice::DiceCommand;
+
+	#[test]
+	fn outcomes() {
+		use GameSystem::*;
+
+		assert_eq!(Dnd5e.outcome(25, &[20], None), Some("natural 20, critical hit!"));
+		assert_eq!(Dnd5e.outcome(6, &[1], None), Some("natural 1, critical miss"));
+		assert_eq!(Dnd5e.outcome(15, &[10], None), None);
+
+		assert_eq!(Pbta.outcome(6, &[3, 3], None), Some("miss"));
+		assert_eq!(Pbta.outcome(9, &[3, 4], None), Some("weak hit"));
+		assert_eq!(Pbta.outcome(11, &[5, 4], None), Some("strong hit"));
+		assert_eq!(Pbta.outcome(11, &[5, 4, 2], None), None);
+
+		assert_eq!(Blades.outcome(0, &[6, 2, 6], None), Some("critical success"));
+		assert_eq!(Blades.outcome(0, &[6, 2], None), Some("full success"));
+		assert_eq!(Blades.outcome(0, &[5, 2], None), Some("partial success"));
+		assert_eq!(Blades.outcome(0, &[3], None), Some("bad outcome"));
+		assert_eq!(Blades.outcome(0, &[], None), None);
+
+		assert_eq!(Cypher.outcome(1, &[1], None), Some("GM intrusion"));
+		assert_eq!(Cypher.outcome(24, &[19], None), Some("minor effect"));
+		assert_eq!(Cypher.outcome(12, &[12], None), None);
+
+		assert_eq!(Cthulhu.outcome(1, &[1], Some(40)), Some("critical success"));
+		assert_eq!(Cthulhu.outcome(12, &[12], Some(60)), Some("extreme success"));
+		assert_eq!(Cthulhu.outcome(30, &[30], Some(60)), Some("hard success"));
+		assert_eq!(Cthulhu.outcome(55, &[55], Some(60)), Some("regular success"));
+		assert_eq!(Cthulhu.outcome(61, &[61], Some(60)), Some("failure"));
+		assert_eq!(Cthulhu.outcome(97, &[97], Some(40)), Some("fumble"));
+		assert_eq!(Cthulhu.outcome(97, &[97], Some(60)), Some("failure"));
+		assert_eq!(Cthulhu.outcome(100, &[100], None), Some("fumble"));
+		assert_eq!(Cthulhu.outcome(50, &[50], None), None);
+	}
+
+	#[test]
+	fn faces() {
+		//	Only the kept d20 counts, and the modifier doesn't
+		for seed in 0..100 {
+			let command: DiceCommand = "2d20kh1 + 5".parse().unwrap();
+			let roll = command.roll(&mut StdRng::seed_from_u64(seed)).unwrap();
+			let faces = roll.kept_faces(20);
+
+			assert_eq!(faces.len(), 1);
+			assert_eq!(faces[0] + 5, roll.total);
+			assert!(roll.kept_faces(6).is_empty());
+		}
+
+		assert_eq!("Blades".parse::<GameSystem>(), Ok(GameSystem::Blades));
+		assert!("gurps".parse::<GameSystem>().is_err());
+	}
+}
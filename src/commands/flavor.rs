@@ -2,12 +2,6 @@
 use twilight_model::{
 	guild::Role,
 	gateway::payload::incoming::MessageCreate,
-	channel::message::{
-		Message,
-		component::{
-			Button, ButtonStyle, Component, ComponentType
-		}
-	},
 	id::{
 		Id, marker::{
 			GuildMarker,
@@ -40,11 +34,13 @@ pub async fn flavor(
 	let user_id: Id<UserMarker> = msg.author.id;
 
 	//	Retrieve guild data
-	let guild_id: Id<GuildMarker> = msg.guild_id.expect("Message not in guild");
-	let mut guild_data: GuildData = match GuildData::read_file(guild_id).await {
-		Ok(res) => res,
-		Err(_) => GuildData::new(guild_id).await
+	let Some(guild_id) = msg.guild_id else {
+		ctx.http.create_message(msg.channel_id)
+			.content("flavor roles belong to a server, so this only works in one")?.await?;
+		return Ok(())
 	};
+	let _lock = GuildData::lock(guild_id).await;
+	let mut guild_data: GuildData = GuildData::read_or_new(guild_id).await?;
 	let role_id = guild_data.flavor_map.get(&user_id).cloned();
 
 	//	Get role (or create default)
@@ -78,7 +74,7 @@ pub async fn flavor(
 	};
 	
 	//	Split args
-	let args = rest.split(' ');
+	let args = rest.split_whitespace();
 	for mut arg in args {
 		arg = arg.trim();
 		match arg.split_once(':') {
@@ -117,6 +113,7 @@ pub async fn flavor(
 	Ok(())
 }
 
+#[allow(dead_code)]
 async fn push_role_forward(
 	ctx: InteractionContext, 
 	role_id: Id<RoleMarker>,
@@ -0,0 +1,351 @@
+//		Imports
+use std::fmt;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use twilight_model::{
+	channel::message::AllowedMentions,
+	gateway::payload::incoming::MessageCreate,
+	id::{
+		Id, marker::{
+			MessageMarker,
+			UserMarker
+		}
+	}
+};
+
+use crate::{
+	BotResult,
+	InteractionContext,
+	commands::dice::{GameMaster, MAX_MESSAGE, roll_for, truncate},
+	data::ChannelData
+};
+
+//		Data
+//	Long enough for any character, short enough that the order fits in a message
+const MAX_NAME: usize = 64;
+
+//	The turn order of one channel's fight.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Initiative {
+	//	Kept sorted, highest first
+	pub combatants:Vec<Combatant>,
+	//	Index of whoever's turn it is
+	pub turn:usize,
+	//	Zero until the first "next"
+	pub round:u32,
+
+	//	The message showing the order, which gets edited as things change
+	pub message:Option<Id<MessageMarker>>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Combatant {
+	pub name:String,
+	//	Players join as themselves; NPCs have no user
+	pub user:Option<Id<UserMarker>>,
+
+	pub total:i32,
+	pub modifier:i32,
+	//	Rolled on joining, to settle any tie the modifier doesn't
+	pub tiebreak:u32
+}
+
+//		Command
+pub async fn init(
+	ctx: InteractionContext,
+	msg: Box<MessageCreate>,
+	rest: &str
+) -> BotResult<()> {
+	let _lock = ChannelData::lock(msg.channel_id).await;
+	let mut channel: ChannelData = ChannelData::read_or_new(msg.channel_id).await?;
+
+	let (sub, args) = rest.split_once(' ').unwrap_or((rest, ""));
+	let args = args.trim();
+	let reply: Option<String> = match sub {
+		"join" => {
+			let (expr, name) = match args.split_once(" as ") {
+				Some((expr, name)) => (expr.trim(), name.trim().to_owned()),
+				None => (args, format!("<@{}>", msg.author.id))
+			};
+
+			match check_name(&name) {
+				Err(e) => Some(e),
+				Ok(()) => match roll_for(msg.guild_id, msg.author.id, &init_expr(expr)).await {
+					Ok((total, roll)) => {
+						channel.initiative.add(Combatant::new(name.clone(), Some(msg.author.id), total, modifier(expr)));
+						Some(format!("{} rolled initiative: {}", name, roll))
+					}
+					Err(e) => Some(e)
+				}
+			}
+		}
+		"add" => match args.rsplit_once(' ') {
+			Some((name, _)) if check_name(name.trim()).is_err() => check_name(name.trim()).err(),
+			Some((name, expr)) => match roll_for(msg.guild_id, msg.author.id, &init_expr(expr)).await {
+				Ok((total, roll)) => {
+					let name = name.trim().to_owned();
+					channel.initiative.add(Combatant::new(name.clone(), None, total, modifier(expr)));
+					Some(format!("{} rolled initiative: {}", name, roll))
+				}
+				Err(e) => Some(e)
+			},
+			None => Some("usage: init add <name> <modifier>".to_string())
+		},
+		"remove" => match channel.initiative.remove(args) {
+			true => None,
+			false => Some(format!("there's nobody called {} in the order", args))
+		},
+		"next" => match channel.initiative.next() {
+			Some(combatant) => Some(format!("{}, you're up", combatant.name)),
+			None => Some("nobody has joined initiative yet".to_string())
+		},
+		"show" => {
+			//	Start a fresh message at the bottom of the channel
+			channel.initiative.message = None;
+			None
+		}
+		"end" | "clear" => {
+			channel.initiative = Initiative::default();
+			channel.write_file().await?;
+			ctx.http.create_message(msg.channel_id).content("combat is over")?.await?;
+			return Ok(())
+		}
+		_ => Some("usage: init join [modifier] [as <name>] | add <name> <modifier> | remove <name> | next | show | end".to_string())
+	};
+
+	//	Saved before anything is sent, so a message that fails doesn't lose the change
+	channel.write_file().await?;
+
+	if let Some(reply) = reply {
+		ctx.http.create_message(msg.channel_id)
+			.allowed_mentions(Some(&AllowedMentions::default()))
+			.content(&truncate(&reply, MAX_MESSAGE))?
+			.await?;
+	}
+
+	let shown = channel.initiative.message;
+	show(&ctx, &mut channel).await?;
+	if channel.initiative.message != shown {
+		channel.write_file().await?;
+	}
+
+	Ok(())
+}
+
+//	Edits the order message, or posts & pins a new one if there isn't one to edit.
+async fn show(ctx: &InteractionContext, channel: &mut ChannelData) -> BotResult<()> {
+	let text = truncate(&channel.initiative.to_string(), MAX_MESSAGE);
+	let edited = match channel.initiative.message {
+		Some(message_id) => ctx.http.update_message(channel.id, message_id)
+			.allowed_mentions(Some(&AllowedMentions::default()))
+			.content(Some(&text))?
+			.await
+			.is_ok(),
+		None => false
+	};
+	if edited { return Ok(()) }
+
+	let message = ctx.http.create_message(channel.id)
+		.allowed_mentions(Some(&AllowedMentions::default()))
+		.content(&text)?
+		.await?
+		.model().await?;
+	channel.initiative.message = Some(message.id);
+
+	//	Pinning needs Manage Messages, which the bot may not have
+	let _ = ctx.http.create_pin(channel.id, message.id).await;
+
+	Ok(())
+}
+
+//	"+3" or "3" is a modifier on a d20, anything else is its own roll.
+fn init_expr(arg: &str) -> String {
+	match arg.trim() {
+		"" => "1d20".to_string(),
+		arg if arg.starts_with(['+', '-']) && arg[1..].parse::<u32>().is_ok() => format!("1d20{}", arg),
+		arg if arg.parse::<u32>().is_ok() => format!("1d20+{}", arg),
+		arg => arg.to_string()
+	}
+}
+
+fn modifier(arg: &str) -> i32 {
+	arg.trim().trim_start_matches('+').parse().unwrap_or(0)
+}
+
+fn check_name(name: &str) -> Result<(), String> {
+	match !name.is_empty() && name.chars().count() <= MAX_NAME {
+		true => Ok(()),
+		false => Err(format!("names in the order are 1 to {} characters", MAX_NAME))
+	}
+}
+
+impl Combatant {
+	pub fn new(name: String, user: Option<Id<UserMarker>>, total: i32, modifier: i32) -> Self {
+		Combatant{ name, user, total, modifier, tiebreak: rand::thread_rng().gen() }
+	}
+
+	//	Sorts before `other` in the order.
+	fn before(&self, other: &Combatant) -> bool {
+		(self.total, self.modifier, self.tiebreak) > (other.total, other.modifier, other.tiebreak)
+	}
+}
+
+impl Initiative {
+	//	Adds someone in order, replacing any earlier entry of theirs. Whoever's turn it
+	//	is keeps it.
+	pub fn add(&mut self, combatant: Combatant) {
+		let name = combatant.name.clone();
+		self.remove(&name);
+
+		let index = self.combatants.iter()
+			.position(|other| combatant.before(other))
+			.unwrap_or(self.combatants.len());
+		if index <= self.turn && self.round > 0 && !self.combatants.is_empty() {
+			self.turn += 1;
+		}
+		self.combatants.insert(index, combatant);
+	}
+
+	//	Takes someone out by name, case-insensitively, or a player by their mention
+	//	whatever name they joined as. The turn passes to whoever was after them if it
+	//	was theirs.
+	pub fn remove(&mut self, name: &str) -> bool {
+		let user = match GameMaster::from_mention(name.trim()) {
+			Some(GameMaster::User(id)) => Some(id),
+			_ => None
+		};
+		let Some(index) = self.combatants.iter().position(|c| match user {
+			Some(user) => c.user == Some(user),
+			None => c.name.eq_ignore_ascii_case(name)
+		}) else {
+			return false
+		};
+		self.combatants.remove(index);
+
+		if index < self.turn { self.turn -= 1; }
+		if self.turn >= self.combatants.len() { self.turn = 0; }
+
+		true
+	}
+
+	//	Moves to the next turn, starting a new round after the last combatant.
+	pub fn next(&mut self) -> Option<&Combatant> {
+		if self.combatants.is_empty() { return None }
+
+		if self.round == 0 {
+			self.round = 1;
+			self.turn = 0;
+		} else {
+			self.turn += 1;
+			if self.turn >= self.combatants.len() {
+				self.turn = 0;
+				self.round += 1;
+			}
+		}
+
+		self.combatants.get(self.turn)
+	}
+}
+
+impl fmt::Display for Initiative {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.round {
+			0 => writeln!(f, "**initiative** (not started; `!init next` to begin)")?,
+			round => writeln!(f, "**initiative**, round {}", round)?,
+		}
+		if self.combatants.is_empty() {
+			return write!(f, "nobody yet; `!init join` or `!init add <name> <modifier>`")
+		}
+
+		for (i, combatant) in self.combatants.iter().enumerate() {
+			let marker = if self.round > 0 && i == self.turn { "▶" } else { "　" };
+			writeln!(f, "{} {}. {} ({})", marker, i + 1, combatant.name, combatant.total)?;
+		}
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn combatant(name: &str, total: i32, modifier: i32, tiebreak: u32) -> Combatant {
+		Combatant{ name: name.to_owned(), user: None, total, modifier, tiebreak }
+	}
+
+	fn names(init: &Initiative) -> Vec<&str> {
+		init.combatants.iter().map(|c| c.name.as_str()).collect()
+	}
+
+	#[test]
+	fn order() {
+		let mut init = Initiative::default();
+		init.add(combatant("goblin", 12, 2, 0));
+		init.add(combatant("fighter", 18, 1, 0));
+		init.add(combatant("rogue", 12, 4, 0));
+		init.add(combatant("wizard", 12, 2, 7));
+
+		//	Ties go to the higher modifier, then the tiebreak roll
+		assert_eq!(names(&init), ["fighter", "rogue", "wizard", "goblin"]);
+
+		//	Rejoining replaces the old roll
+		init.add(combatant("Goblin", 20, 2, 0));
+		assert_eq!(names(&init), ["Goblin", "fighter", "rogue", "wizard"]);
+	}
+
+	#[test]
+	fn turns() {
+		let mut init = Initiative::default();
+		assert_eq!(init.next(), None);
+
+		init.add(combatant("a", 20, 0, 0));
+		init.add(combatant("b", 15, 0, 0));
+		init.add(combatant("c", 10, 0, 0));
+
+		assert_eq!(init.next().unwrap().name, "a");
+		assert_eq!(init.round, 1);
+		assert_eq!(init.next().unwrap().name, "b");
+
+		//	Someone joining ahead of the current turn doesn't steal it
+		init.add(combatant("d", 25, 0, 0));
+		assert_eq!(init.combatants[init.turn].name, "b");
+
+		//	Removing whoever's up passes the turn on
+		assert!(init.remove("b"));
+		assert_eq!(init.combatants[init.turn].name, "c");
+		assert!(!init.remove("b"));
+
+		assert_eq!(init.next().unwrap().name, "d");
+		assert_eq!(init.round, 2);
+		assert!(init.to_string().contains("round 2"));
+	}
+
+	#[test]
+	fn players() {
+		let mut init = Initiative::default();
+		init.add(Combatant{ user: Some(Id::new(7)), ..combatant("Aria", 15, 0, 0) });
+		init.add(Combatant{ user: Some(Id::new(8)), ..combatant("<@8>", 12, 0, 0) });
+
+		//	However they're mentioned, and whatever they joined as
+		assert!(init.remove("<@!7>"));
+		assert!(!init.remove("<@9>"));
+		assert!(init.remove("<@8>"));
+		assert!(init.combatants.is_empty());
+
+		assert!(check_name("goblin").is_ok());
+		assert!(check_name("").is_err());
+		assert!(check_name(&"a".repeat(MAX_NAME + 1)).is_err());
+	}
+
+	#[test]
+	fn modifiers() {
+		assert_eq!(init_expr(""), "1d20");
+		assert_eq!(init_expr("+3"), "1d20+3");
+		assert_eq!(init_expr("-1"), "1d20-1");
+		assert_eq!(init_expr("4"), "1d20+4");
+		assert_eq!(init_expr("1d20+@dex"), "1d20+@dex");
+		assert_eq!((modifier("+3"), modifier("-1"), modifier("2d6")), (3, -1, 0));
+	}
+}
@@ -1,2 +1,7 @@
+pub mod deck;
 pub mod dice;
-pub mod flavor;
\ No newline at end of file
+pub mod flavor;
+pub mod init;
+pub mod random;
+pub mod table;
+pub mod var;
\ No newline at end of file
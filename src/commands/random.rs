@@ -0,0 +1,184 @@
+//		Imports
+use rand::{Rng, seq::SliceRandom};
+
+use twilight_model::{
+	channel::message::AllowedMentions,
+	gateway::payload::incoming::MessageCreate
+};
+
+use crate::{
+	BotResult,
+	InteractionContext,
+	commands::dice::{MAX_MESSAGE, rng, take_option, truncate}
+};
+
+//		Data
+const MAX_ITEMS: usize = 100;
+const MAX_FLIPS: u32 = 1000;
+//	Flips beyond this are only counted, not listed
+const MAX_SHOWN_FLIPS: u32 = 20;
+
+//		Command
+//	"choose", "shuffle" and "flip" all take "--seed N" the way dice do.
+pub async fn random(
+	ctx: InteractionContext,
+	msg: Box<MessageCreate>,
+	name: &str,
+	rest: &str
+) -> BotResult<()> {
+	ctx.http.create_message(msg.channel_id)
+		.allowed_mentions(Some(&AllowedMentions::default()))
+		.content(&random_reply(name, rest))?
+		.await?;
+
+	Ok(())
+}
+
+//		Functions
+//	The whole reply to one of the commands, cut down to fit in a message.
+fn random_reply(name: &str, rest: &str) -> String {
+	let reply = match take_option(rest, "--seed") {
+		Ok((rest, seed)) => {
+			let mut rng = rng(seed);
+			let reply = match name {
+				"choose" => choose_reply(&rest, &mut rng),
+				"shuffle" => shuffle_reply(&rest, &mut rng),
+				_ => flip_reply(&rest, &mut rng)
+			};
+			match (reply, seed) {
+				(Ok(reply), Some(seed)) => format!("{} (seed {})", reply, seed),
+				(Ok(reply), None) => reply,
+				(Err(e), _) => e
+			}
+		}
+		Err(e) => e
+	};
+
+	truncate(&reply, MAX_MESSAGE)
+}
+
+//	Items are separated by commas, or by spaces if there are none.
+fn items(rest: &str) -> Result<Vec<&str>, String> {
+	let items: Vec<&str> = match rest.contains(',') {
+		true => rest.split(',').map(str::trim).filter(|item| !item.is_empty()).collect(),
+		false => rest.split_whitespace().collect()
+	};
+
+	match items.len() {
+		0 => Err("give me some things to pick from, separated by commas".to_string()),
+		n if n > MAX_ITEMS => Err(format!("that's too many things; at most {}", MAX_ITEMS)),
+		_ => Ok(items)
+	}
+}
+
+//	"choose a, b, c", where "b:3" makes b three times as likely.
+fn choose_reply(rest: &str, rng: &mut (impl Rng + ?Sized)) -> Result<String, String> {
+	let weighted: Vec<(&str, u32)> = items(rest)?.into_iter()
+		.map(|item| match item.rsplit_once(':') {
+			Some((name, weight)) => match weight.trim().parse::<u32>() {
+				Ok(weight) => Ok((name.trim(), weight)),
+				Err(_) => Err(format!("`{}` needs a whole number after the colon", item))
+			},
+			None => Ok((item, 1))
+		})
+		.collect::<Result<_, _>>()?;
+
+	let total = weighted.iter().try_fold(0u32, |sum, (_, weight)| sum.checked_add(*weight))
+		.filter(|&total| total > 0)
+		.ok_or("the weights have to add up to more than 0".to_string())?;
+
+	let mut roll = rng.gen_range(0..total);
+	for (item, weight) in &weighted {
+		if roll < *weight { return Ok(format!("you chose: {}", item)) }
+		roll -= weight;
+	}
+
+	unreachable!("rolls fall within the total weight")
+}
+
+//	"shuffle a, b, c" - a random order, say for marching.
+fn shuffle_reply(rest: &str, rng: &mut (impl Rng + ?Sized)) -> Result<String, String> {
+	let mut items = items(rest)?;
+	items.shuffle(rng);
+
+	let lines: Vec<String> = items.iter().enumerate()
+		.map(|(i, item)| format!("{}. {}", i + 1, item))
+		.collect();
+	Ok(format!("you shuffled:\n{}", lines.join("\n")))
+}
+
+//	"flip [n]" - one coin, or a handful of them.
+fn flip_reply(rest: &str, rng: &mut (impl Rng + ?Sized)) -> Result<String, String> {
+	let count = match rest.trim() {
+		"" => 1,
+		n => n.parse::<u32>().ok()
+			.filter(|n| (1..=MAX_FLIPS).contains(n))
+			.ok_or(format!("you can flip 1 to {} coins", MAX_FLIPS))?
+	};
+
+	let flips: Vec<bool> = (0..count).map(|_| rng.gen()).collect();
+	let heads = flips.iter().filter(|&&heads| heads).count();
+	let name = |heads: bool| if heads { "heads" } else { "tails" };
+
+	Ok(match count {
+		1 => format!("you flipped: {}", name(flips[0])),
+		n if n <= MAX_SHOWN_FLIPS => {
+			let shown: Vec<&str> = flips.iter().map(|&heads| name(heads)).collect();
+			format!("you flipped {} coins: {} ({} heads, {} tails)", n, shown.join(", "), heads, n as usize - heads)
+		}
+		n => format!("you flipped {} coins: {} heads, {} tails", n, heads, n as usize - heads)
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn choosing() {
+		for seed in 0..50 {
+			let reply = choose_reply("alice, bob, carol", &mut rng(Some(seed))).unwrap();
+			assert!(["you chose: alice", "you chose: bob", "you chose: carol"].contains(&reply.as_str()));
+
+			//	Only one thing can come up
+			assert_eq!(choose_reply("never:0, always:5", &mut rng(Some(seed))), Ok("you chose: always".to_owned()));
+		}
+
+		assert_eq!(choose_reply("one two", &mut rng(Some(0))).unwrap().split(' ').count(), 3);
+		assert!(choose_reply("a:x, b", &mut rng(Some(0))).is_err());
+		assert!(choose_reply("a:0", &mut rng(Some(0))).is_err());
+		assert!(choose_reply(" , ", &mut rng(Some(0))).is_err());
+
+		//	The same seed, the same choice
+		assert_eq!(
+			choose_reply("a, b, c, d, e, f", &mut rng(Some(7))),
+			choose_reply("a, b, c, d, e, f", &mut rng(Some(7)))
+		);
+	}
+
+	#[test]
+	fn shuffling() {
+		let reply = shuffle_reply("a, b, c, d", &mut rng(Some(1))).unwrap();
+		let mut items: Vec<&str> = reply.lines().skip(1).map(|line| &line[3..]).collect();
+		items.sort();
+		assert_eq!(items, ["a", "b", "c", "d"]);
+
+		//	A hundred long items are more than a message holds
+		let long = vec!["x".repeat(50); MAX_ITEMS].join(", ");
+		let reply = random_reply("shuffle", &format!("{} --seed 1", long));
+		assert_eq!(reply.chars().count(), MAX_MESSAGE);
+		assert!(reply.ends_with('…'));
+	}
+
+	#[test]
+	fn flipping() {
+		assert!(flip_reply("", &mut rng(Some(0))).unwrap().starts_with("you flipped: "));
+
+		let reply = flip_reply("10", &mut rng(Some(0))).unwrap();
+		assert_eq!(reply.matches("heads").count() + reply.matches("tails").count(), 12);
+
+		assert!(flip_reply("1000", &mut rng(Some(0))).unwrap().ends_with(" tails"));
+		assert!(flip_reply("0", &mut rng(Some(0))).is_err());
+		assert!(flip_reply("1001", &mut rng(Some(0))).is_err());
+	}
+}
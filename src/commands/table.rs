@@ -0,0 +1,422 @@
+//		Imports
+use std::collections::HashMap;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use twilight_model::{
+	channel::message::AllowedMentions,
+	gateway::payload::incoming::MessageCreate
+};
+
+use crate::{
+	BotResult,
+	InteractionContext,
+	commands::dice::{DiceCommand, MAX_MESSAGE, RollLimits, truncate},
+	data::GuildData,
+	permissions::can_manage_guild
+};
+
+//		Data
+//	A list of results to pick from at random, some more likely than others.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RandomTable {
+	pub entries:Vec<TableEntry>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TableEntry {
+	pub weight:u32,
+	//	May hold dice, "3d6 gold" or "[[2d4 + 1]] goblins", and other tables, "{weather}"
+	pub text:String
+}
+
+//	How a table can be written in JSON: a list of entries, each either plain text or
+//	an object with a weight
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonEntry {
+	Text(String),
+	Weighted{ weight:u32, text:String },
+}
+
+const MAX_ENTRIES: usize = 1000;
+const MAX_TABLES: usize = 100;
+const MAX_ENTRY_LENGTH: usize = 300;
+//	Times one message can roll on a table
+const MAX_ROLLS: u32 = 20;
+//	How deep tables can roll on other tables, which also stops them rolling on themselves forever
+const MAX_DEPTH: u32 = 8;
+//	Tables one message can roll on in all, counting every one rolled on by another
+const MAX_EXPANSIONS: u32 = 200;
+
+//		Command
+pub async fn table(
+	ctx: InteractionContext,
+	msg: Box<MessageCreate>,
+	rest: &str
+) -> BotResult<()> {
+	let Some(guild_id) = msg.guild_id else {
+		ctx.http.create_message(msg.channel_id)
+			.content("tables are kept per server, so they only work in one")?.await?;
+		return Ok(())
+	};
+
+	let _lock = GuildData::lock(guild_id).await;
+	let mut guild_data: GuildData = GuildData::read_or_new(guild_id).await?;
+
+	//	Table definitions run over several lines, so split on any whitespace
+	let rest = rest.trim();
+	let (sub, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+	let (name, body) = args.trim().split_once(char::is_whitespace).unwrap_or((args.trim(), ""));
+	let name = name.to_lowercase();
+
+	let reply: String = match sub {
+		"roll" => {
+			let times = match body.trim() {
+				"" => Ok(1),
+				n => n.parse::<u32>().ok().filter(|n| (1..=MAX_ROLLS).contains(n))
+					.ok_or(format!("you can roll on a table 1 to {} times at once", MAX_ROLLS))
+			};
+			match times {
+				Ok(times) => roll_reply(&guild_data.tables, &name, times, &guild_data.roll_limits, &mut rand::thread_rng()),
+				Err(e) => e
+			}
+		}
+		"list" => {
+			let mut names: Vec<String> = guild_data.tables.iter()
+				.map(|(name, table)| format!("{} ({} entries)", name, table.entries.len()))
+				.collect();
+			names.sort();
+			match names.is_empty() {
+				true => "this server has no tables yet; a server manager can add one with `!table set <name>`".to_string(),
+				false => names.join("\n")
+			}
+		}
+		"show" => match guild_data.tables.get(&name) {
+			Some(table) => {
+				//	As many entries as fit, leaving room for the rest to be counted
+				let mut lines: Vec<String> = vec![];
+				let mut length = 0;
+				for entry in &table.entries {
+					let line = format!("{}, {}", entry.weight, entry.text);
+					length += line.chars().count() + 1;
+					if length > MAX_MESSAGE - 100 { break }
+					lines.push(line);
+				}
+				let more = match table.entries.len() - lines.len() {
+					0 => String::new(),
+					n => format!("\n…and {} more", n)
+				};
+				format!("**{}**\n```\n{}\n```{}", name, lines.join("\n"), more)
+			}
+			None => format!("there's no table called {}", name)
+		},
+		"set" | "delete" => {
+			let roles = msg.member.as_ref().map(|member| member.roles.clone()).unwrap_or_default();
+			if !can_manage_guild(&ctx, guild_id, msg.author.id, &roles).await? {
+				"only server managers can change tables".to_string()
+			} else if sub == "delete" {
+				match guild_data.tables.remove(&name) {
+					Some(_) => {
+						guild_data.write_file().await?;
+						format!("deleted the {} table", name)
+					}
+					None => format!("there's no table called {}", name)
+				}
+			} else {
+				match check_name(&name).and_then(|_| RandomTable::parse(body)) {
+					Ok(_) if !guild_data.tables.contains_key(&name) && guild_data.tables.len() >= MAX_TABLES =>
+						format!("a server can have at most {} tables", MAX_TABLES),
+					Ok(table) => {
+						let count = table.entries.len();
+						guild_data.tables.insert(name.clone(), table);
+						guild_data.write_file().await?;
+						format!("saved the {} table with {} entries", name, count)
+					}
+					Err(e) => e
+				}
+			}
+		}
+		_ => "usage: table roll <name> [times] | list | show <name> | set <name> <entries> | delete <name>\n\
+			entries go one per line as `weight, text` or `low-high, text`, or as a JSON list".to_string()
+	};
+
+	ctx.http.create_message(msg.channel_id)
+		.allowed_mentions(Some(&AllowedMentions::default()))
+		.content(&truncate(&reply, MAX_MESSAGE))?
+		.await?;
+
+	Ok(())
+}
+
+//		Functions
+fn check_name(name: &str) -> Result<(), String> {
+	match !name.is_empty() && name.chars().count() <= 32 && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+		true => Ok(()),
+		false => Err("table names are up to 32 letters, digits, dashes or underscores".to_string())
+	}
+}
+
+impl RandomTable {
+	//	Reads a table pasted into a message, either as JSON or as one entry per line.
+	//	Lines start with a weight, or a range like "1-4" as printed tables have; lines
+	//	without either have a weight of 1. Code blocks around the whole thing are ignored.
+	pub fn parse(s: &str) -> Result<Self, String> {
+		let s = s.trim()
+			.trim_start_matches("```json").trim_start_matches("```csv").trim_start_matches("```")
+			.trim_end_matches("```")
+			.trim();
+
+		let entries: Vec<TableEntry> = match s.starts_with('[') {
+			true => serde_json::from_str::<Vec<JsonEntry>>(s)
+				.map_err(|e| format!("that isn't a JSON list of entries: {}", e))?
+				.into_iter()
+				.map(|entry| match entry {
+					JsonEntry::Text(text) => TableEntry{ weight: 1, text },
+					JsonEntry::Weighted{ weight, text } => TableEntry{ weight, text }
+				})
+				.collect(),
+			false => s.lines()
+				.map(str::trim)
+				.filter(|line| !line.is_empty())
+				.map(TableEntry::parse_line)
+				.collect::<Result<_, _>>()?
+		};
+
+		if entries.is_empty() { return Err("a table needs at least one entry".to_string()) }
+		if entries.len() > MAX_ENTRIES { return Err(format!("tables can have at most {} entries", MAX_ENTRIES)) }
+		if let Some(entry) = entries.iter().find(|entry| entry.text.trim().is_empty() || entry.text.chars().count() > MAX_ENTRY_LENGTH) {
+			return Err(format!("entries need some text, up to {} characters: `{}`", MAX_ENTRY_LENGTH, entry.text))
+		}
+		if entries.iter().try_fold(0u32, |sum, entry| sum.checked_add(entry.weight)).unwrap_or(0) == 0 {
+			return Err("a table's weights have to add up to more than 0, and not overflow".to_string())
+		}
+
+		Ok(RandomTable{ entries })
+	}
+
+	//	Tables saved on disk aren't checked again when they're loaded, so one edited by
+	//	hand may have nothing to pick.
+	fn pick(&self, rng: &mut (impl Rng + ?Sized)) -> Option<&TableEntry> {
+		let total = self.entries.iter().try_fold(0u32, |sum, entry| sum.checked_add(entry.weight))?;
+		if total == 0 { return None }
+
+		let mut roll = rng.gen_range(0..total);
+		for entry in &self.entries {
+			if roll < entry.weight { return Some(entry) }
+			roll -= entry.weight;
+		}
+
+		unreachable!("rolls fall within the total weight")
+	}
+}
+
+impl TableEntry {
+	fn parse_line(line: &str) -> Result<Self, String> {
+		let Some((weight, text)) = line.split_once(',') else {
+			return Ok(TableEntry{ weight: 1, text: line.to_owned() })
+		};
+
+		let weight = match weight.trim().split_once('-') {
+			Some((low, high)) => match (low.trim().parse::<u32>(), high.trim().parse::<u32>()) {
+				(Ok(low), Ok(high)) if low <= high => match (high - low).checked_add(1) {
+					Some(weight) => Some(weight),
+					None => return Err(format!("`{}` is too wide a range", weight.trim()))
+				},
+				_ => None
+			},
+			None => weight.trim().parse::<u32>().ok()
+		};
+
+		Ok(match weight {
+			Some(weight) => TableEntry{ weight, text: text.trim().to_owned() },
+			//	Just text with a comma in it
+			None => TableEntry{ weight: 1, text: line.to_owned() }
+		})
+	}
+}
+
+fn roll_reply(
+	tables: &HashMap<String, RandomTable>,
+	name: &str,
+	times: u32,
+	limits: &RollLimits,
+	rng: &mut (impl Rng + ?Sized)
+) -> String {
+	if !tables.contains_key(name) { return format!("there's no table called {}", name) }
+
+	let mut budget = MAX_EXPANSIONS;
+	let results: Vec<String> = (0..times)
+		.map(|_| roll_on(tables, name, limits, rng, 0, &mut budget))
+		.collect();
+
+	match &results[..] {
+		[result] => format!("**{}**: {}", name, result),
+		results => {
+			let lines: Vec<String> = results.iter().enumerate()
+				.map(|(i, result)| format!("{}. {}", i + 1, result))
+				.collect();
+			format!("**{}** ×{}:\n{}", name, times, lines.join("\n"))
+		}
+	}
+}
+
+//	Picks an entry and fills it in: "[[expr]]" and words like "3d6" are rolled, and
+//	"{table}" rolls on that table in turn, so long as the budget of tables lasts.
+fn roll_on(
+	tables: &HashMap<String, RandomTable>,
+	name: &str,
+	limits: &RollLimits,
+	rng: &mut (impl Rng + ?Sized),
+	depth: u32,
+	budget: &mut u32
+) -> String {
+	let Some(table) = tables.get(name) else { return format!("{{{}?}}", name) };
+	if depth >= MAX_DEPTH || *budget == 0 { return "…".to_string() }
+	*budget -= 1;
+
+	let Some(TableEntry{ text, .. }) = table.pick(rng) else { return format!("{{{} has nothing to roll}}", name) };
+	let mut out = String::new();
+	let mut rest = text.as_str();
+	while let Some(start) = rest.find(['{', '[']) {
+		let (before, from) = rest.split_at(start);
+		out += &roll_words(before, limits, rng);
+
+		let (close, inner) = match from.starts_with("[[") {
+			true => ("]]", &from[2..]),
+			false => ("}", &from[1..])
+		};
+		match (inner.find(close), from.starts_with('{') || from.starts_with("[[")) {
+			(Some(end), true) => {
+				let inner_text = &inner[..end];
+				out += &match close {
+					"]]" => roll_total(inner_text, limits, rng).unwrap_or_else(|| format!("[[{}]]", inner_text)),
+					_ => roll_on(tables, &inner_text.trim().to_lowercase(), limits, rng, depth + 1, budget)
+				};
+				rest = &inner[end + close.len()..];
+			}
+			_ => {
+				out += &from[..1];
+				rest = &from[1..];
+			}
+		}
+	}
+	out += &roll_words(rest, limits, rng);
+
+	out
+}
+
+//	Rolls the words of plain text that are dice, leaving the rest as written.
+fn roll_words(text: &str, limits: &RollLimits, rng: &mut (impl Rng + ?Sized)) -> String {
+	let words: Vec<String> = text.split(' ')
+		.map(|word| {
+			let bare = word.trim_end_matches([',', '.', ';', ':', '!', '?', ')']);
+			let tail = &word[bare.len()..];
+			match bare.contains('d') {
+				true => match roll_total(bare, limits, rng) {
+					Some(total) => format!("{}{}", total, tail),
+					None => word.to_owned()
+				},
+				false => word.to_owned()
+			}
+		})
+		.collect();
+
+	words.join(" ")
+}
+
+fn roll_total(expr: &str, limits: &RollLimits, rng: &mut (impl Rng + ?Sized)) -> Option<String> {
+	let roll = DiceCommand::parse_with(expr, limits).ok()?.roll(rng).ok()?;
+	Some(roll.total.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rand::{SeedableRng, rngs::StdRng};
+
+	fn tables(defs: &[(&str, &str)]) -> HashMap<String, RandomTable> {
+		defs.iter()
+			.map(|(name, body)| (name.to_string(), RandomTable::parse(body).unwrap()))
+			.collect()
+	}
+
+	#[test]
+	fn parsing() {
+		let table = RandomTable::parse("```\n1-4, goblins\n5, an ogre\n2, rain, then fog\nnothing\n```").unwrap();
+		assert_eq!(table.entries.iter().map(|e| e.weight).collect::<Vec<_>>(), [4, 5, 2, 1]);
+		assert_eq!(table.entries[2].text, "rain, then fog");
+
+		let table = RandomTable::parse(r#"["a sword", {"weight": 3, "text": "3d6 gold"}]"#).unwrap();
+		assert_eq!(table.entries[1], TableEntry{ weight: 3, text: "3d6 gold".to_owned() });
+
+		assert!(RandomTable::parse("").is_err());
+		assert!(RandomTable::parse("0, never").is_err());
+		assert!(RandomTable::parse("[1, 2]").is_err());
+		assert!(RandomTable::parse("0-4294967295, everything").is_err());
+		assert_eq!(RandomTable::parse("0-4294967294, everything").unwrap().entries[0].weight, u32::MAX);
+
+		assert!(check_name("ÿ".repeat(32).as_str()).is_ok());
+		assert!(check_name("ÿ".repeat(33).as_str()).is_err());
+	}
+
+	#[test]
+	fn rolling() {
+		let tables = tables(&[
+			("loot", "3d6 gold\n{gem}"),
+			("gem", "a ruby\n[[1d4 + 10]] pearls"),
+			("loop", "and {loop}"),
+		]);
+		let limits = RollLimits::default();
+
+		for seed in 0..50 {
+			let result = roll_on(&tables, "loot", &limits, &mut StdRng::seed_from_u64(seed), 0, &mut MAX_EXPANSIONS.to_owned());
+			let valid = match result.split_once(' ') {
+				Some((n, "gold")) => (3..=18).contains(&n.parse::<i32>().unwrap()),
+				Some((n, "pearls")) => (11..=14).contains(&n.parse::<i32>().unwrap()),
+				_ => result == "a ruby"
+			};
+			assert!(valid, "{}", result);
+		}
+
+		let result = roll_on(&tables, "loop", &limits, &mut StdRng::seed_from_u64(0), 0, &mut MAX_EXPANSIONS.to_owned());
+		assert_eq!(result, format!("{}…", "and ".repeat(MAX_DEPTH as usize)));
+
+		assert_eq!(roll_reply(&tables, "weather", 1, &limits, &mut rand::thread_rng()), "there's no table called weather");
+		assert_eq!(roll_reply(&tables, "gem", 3, &limits, &mut rand::thread_rng()).lines().count(), 4);
+
+		//	Saved tables that were edited into ones with nothing to pick
+		let mut tables = tables;
+		tables.insert("empty".to_owned(), RandomTable::default());
+		tables.insert("zero".to_owned(), RandomTable{ entries: vec![TableEntry{ weight: 0, text: "never".to_owned() }] });
+		tables.insert("huge".to_owned(), RandomTable{ entries: vec![TableEntry{ weight: u32::MAX, text: "x".to_owned() }; 2] });
+		for name in ["empty", "zero", "huge"] {
+			assert_eq!(roll_reply(&tables, name, 1, &limits, &mut rand::thread_rng()), format!("**{}**: {{{} has nothing to roll}}", name, name));
+		}
+	}
+
+	#[test]
+	fn expansions() {
+		//	Eight levels of four would be 4^8 rolls without the budget
+		let tables = tables(&[("a", "{a}{a}{a}{a}x")]);
+		let limits = RollLimits::default();
+
+		let mut budget = MAX_EXPANSIONS;
+		let result = roll_on(&tables, "a", &limits, &mut StdRng::seed_from_u64(0), 0, &mut budget);
+		assert_eq!(budget, 0);
+		assert_eq!(result.matches('x').count(), MAX_EXPANSIONS as usize);
+
+		let reply = roll_reply(&tables, "a", MAX_ROLLS, &limits, &mut StdRng::seed_from_u64(0));
+		assert_eq!(reply.matches('x').count(), MAX_EXPANSIONS as usize);
+	}
+
+	#[test]
+	fn plain_words() {
+		let limits = RollLimits::default();
+		let mut rng = StdRng::seed_from_u64(0);
+
+		assert_eq!(roll_words("a dragon, sleeping", &limits, &mut rng), "a dragon, sleeping");
+		assert_eq!(roll_words("d1 hidden doors.", &limits, &mut rng), "1 hidden doors.");
+		assert_eq!(roll_words("1d1, 2d1!", &limits, &mut rng), "1, 2!");
+	}
+}
@@ -0,0 +1,111 @@
+//		Imports
+use std::collections::HashMap;
+
+use twilight_model::{
+	channel::message::AllowedMentions,
+	gateway::payload::incoming::MessageCreate,
+	id::{
+		Id, marker::GuildMarker
+	}
+};
+
+use crate::{
+	BotResult,
+	InteractionContext,
+	commands::dice::{MAX_MESSAGE, truncate},
+	data::UserData
+};
+
+//		Command
+//	Character variables, used in rolls as "@name". Each user has their own set in
+//	every guild.
+pub async fn var(
+	ctx: InteractionContext,
+	msg: Box<MessageCreate>,
+	rest: &str
+) -> BotResult<()> {
+	let Some(guild_id) = msg.guild_id else {
+		ctx.http.create_message(msg.channel_id)
+			.content("variables are kept per server, so they only work in one")?.await?;
+		return Ok(())
+	};
+
+	let _lock = UserData::lock(msg.author.id).await;
+	let mut user_data: UserData = UserData::read_or_new(msg.author.id).await?;
+
+	let words: Vec<&str> = rest.split_whitespace().collect();
+	let reply: String = match words[..] {
+		["set", name, value] => match (check_name(name), value.parse::<i32>()) {
+			(Err(e), _) => e,
+			(_, Err(_)) => format!("@{} needs a whole number", name),
+			(Ok(()), Ok(value)) => {
+				vars(&mut user_data, guild_id).insert(name.to_owned(), value);
+				user_data.write_file().await?;
+				format!("@{} = {}", name, value)
+			}
+		},
+		["unset" | "get", name] if check_name(name).is_err() => check_name(name).unwrap_err(),
+		["unset", name] => match vars(&mut user_data, guild_id).remove(name) {
+			Some(_) => {
+				user_data.write_file().await?;
+				format!("unset @{}", name)
+			}
+			None => format!("@{} isn't set", name)
+		},
+		["get", name] => match vars(&mut user_data, guild_id).get(name) {
+			Some(value) => format!("@{} = {}", name, value),
+			None => format!("@{} isn't set", name)
+		},
+		["list"] => {
+			let mut lines: Vec<String> = vars(&mut user_data, guild_id).iter()
+				.map(|(name, value)| format!("@{} = {}", name, value))
+				.collect();
+			lines.sort();
+
+			match lines.is_empty() {
+				true => "you have no variables here; try `!var set str 3`".to_string(),
+				false => lines.join("\n"),
+			}
+		}
+		_ => "usage: var set <name> <value> | unset <name> | get <name> | list".to_string()
+	};
+
+	ctx.http.create_message(msg.channel_id)
+		.allowed_mentions(Some(&AllowedMentions::default()))
+		.content(&truncate(&reply, MAX_MESSAGE))?
+		.await?;
+
+	Ok(())
+}
+
+fn vars(user_data: &mut UserData, guild_id: Id<GuildMarker>) -> &mut HashMap<String, i32> {
+	user_data.vars.entry(guild_id).or_default()
+}
+
+//	Same rules as macro names: letters, digits & underscores. "@everyone" and "@here"
+//	would ping the whole server whenever they were echoed back.
+fn check_name(name: &str) -> Result<(), String> {
+	if ["everyone", "here"].iter().any(|reserved| name.eq_ignore_ascii_case(reserved)) {
+		return Err(format!("`{}` can't be a variable name", name))
+	}
+
+	match !name.is_empty() && name.chars().count() <= 32 && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+		true => Ok(()),
+		false => Err("variable names are up to 32 letters, digits or underscores".to_string())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn names() {
+		assert!(check_name("str").is_ok());
+		assert!(check_name("bonus_2").is_ok());
+		assert!(check_name("").is_err());
+		assert!(check_name("a-b").is_err());
+		assert!(check_name("everyone").is_err());
+		assert!(check_name("Here").is_err());
+	}
+}
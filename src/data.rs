@@ -1,16 +1,17 @@
 //		Imports
 use std::{
-	collections::HashMap,
+	collections::{HashMap, VecDeque},
+	hash::Hash,
 	path::Path,
+	sync::{Arc, LazyLock, Mutex},
 	fs, 
 };
 use serde::{Deserialize, Serialize};
-use serde_json;
-
-use time::OffsetDateTime;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
 
 use twilight_model::id::{
 	Id, marker::{
+		ChannelMarker,
 		GuildMarker,
 		RoleMarker,
 		UserMarker,
@@ -18,29 +19,123 @@ use twilight_model::id::{
 };
 
 use crate::{
-	BotResult
+	BotResult,
+	commands::{
+		deck::Deck,
+		dice::{DiceCommand, GameMaster, GameSystem, RollLimits, RollRecord, TieRule},
+		init::Initiative,
+		table::RandomTable
+	}
 };
 
+//		Locks
+//	Several commands change the same file, so each holds that file's lock from reading
+//	it to writing it back. Anything holding more than one takes them guild, then user,
+//	then channel.
+type DataLock = Arc<AsyncMutex<()>>;
+type DataLocks<K> = LazyLock<Mutex<HashMap<K, DataLock>>>;
+
+static GUILD_LOCKS: DataLocks<Id<GuildMarker>> = LazyLock::new(Default::default);
+static USER_LOCKS: DataLocks<Id<UserMarker>> = LazyLock::new(Default::default);
+static CHANNEL_LOCKS: DataLocks<Id<ChannelMarker>> = LazyLock::new(Default::default);
+
+async fn lock<K: Eq + Hash>(locks: &DataLocks<K>, id: K) -> OwnedMutexGuard<()> {
+	let lock = locks.lock()
+		.unwrap_or_else(|poisoned| poisoned.into_inner())
+		.entry(id)
+		.or_default()
+		.clone();
+
+	lock.lock_owned().await
+}
+
 //		Guild Data
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GuildData {
 	pub id: Id<GuildMarker>,
 
 	pub flavor_map: HashMap<Id<UserMarker>, Id<RoleMarker>>,
+
+	#[serde(default)]
+	pub roll_limits: RollLimits,
+
+	//	Guild-wide dice macros, by name
+	#[serde(default)]
+	pub macros: HashMap<String, DiceCommand>,
+
+	//	Whether [[expr]] in ordinary messages gets rolled
+	#[serde(default)]
+	pub inline_rolls: bool,
+
+	//	Who secret rolls are sent to
+	#[serde(default)]
+	pub gm: Option<GameMaster>,
+
+	//	How rolls are read, unless a channel says otherwise
+	#[serde(default)]
+	pub system: Option<GameSystem>,
+
+	//	Whether rolls commit to their seeds ahead of time, so they can be verified
+	#[serde(default)]
+	pub fair_rolls: bool,
+
+	//	Who wins a contest when both sides tie
+	#[serde(default)]
+	pub tie_rule: TieRule,
+
+	//	Random tables, by name
+	#[serde(default)]
+	pub tables: HashMap<String, RandomTable>,
 }
 
 impl GuildData {
+	pub async fn lock(
+		guild_id: Id<GuildMarker>,
+	) -> OwnedMutexGuard<()> {
+		lock(&GUILD_LOCKS, guild_id).await
+	}
+
 	pub async fn new(id: Id<GuildMarker>) -> Self {
 		let out = Self {
 			id,
 
 			flavor_map: HashMap::new(),
+
+			roll_limits: RollLimits::default(),
+
+			macros: HashMap::new(),
+
+			inline_rolls: false,
+
+			gm: None,
+
+			system: None,
+
+			fair_rolls: false,
+
+			tie_rule: TieRule::default(),
+
+			tables: HashMap::new(),
 		};
 		let _ = out.write_file().await;
 
 		out
 	}
 
+	//	The guild's data, made fresh if it has none yet. A file that's there but won't
+	//	parse is an error, rather than something to write over.
+	pub async fn read_or_new(
+		guild_id: Id<GuildMarker>,
+	) -> BotResult<Self> {
+		let path = format!("data/guilds/guild_{}.json", guild_id.get());
+		if Path::new(&path).exists() {
+			Self::read_file(guild_id).await
+				.inspect_err(|e| println!("[DATA] {}", e))
+		} else {
+			Ok(Self::new(guild_id).await)
+		}
+	}
+
 	pub async fn read_file(
 		guild_id: Id<GuildMarker>,
 	) -> BotResult<Self> {
@@ -64,6 +159,8 @@ impl GuildData {
 		if !Path::new(&path).exists() { 
 	//		log::warn!(format!("Path {} does not exist", &path)) 
 		}
+		fs::create_dir_all("data/guilds")
+			.map_err(|e| format!("Error creating data directory: {}", e))?;
 	
 		//	Write
 		let serialized = serde_json::to_string(self)
@@ -76,19 +173,203 @@ impl GuildData {
 	}
 }
 
+//		User Data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserData {
+	pub id: Id<UserMarker>,
+
+	//	Dice macros, which follow the user across guilds
+	#[serde(default)]
+	pub macros: HashMap<String, DiceCommand>,
+
+	//	Character variables for dice rolls, separately for each guild
+	#[serde(default)]
+	pub vars: HashMap<Id<GuildMarker>, HashMap<String, i32>>,
+}
+
+impl UserData {
+	pub async fn lock(
+		user_id: Id<UserMarker>,
+	) -> OwnedMutexGuard<()> {
+		lock(&USER_LOCKS, user_id).await
+	}
+
+	pub async fn new(id: Id<UserMarker>) -> Self {
+		let out = Self {
+			id,
+
+			macros: HashMap::new(),
+
+			vars: HashMap::new(),
+		};
+		let _ = out.write_file().await;
+
+		out
+	}
+
+	//	As with guilds, a file that won't parse is an error rather than a fresh start.
+	pub async fn read_or_new(
+		user_id: Id<UserMarker>,
+	) -> BotResult<Self> {
+		let path = format!("data/users/user_{}.json", user_id.get());
+		if Path::new(&path).exists() {
+			Self::read_file(user_id).await
+				.inspect_err(|e| println!("[DATA] {}", e))
+		} else {
+			Ok(Self::new(user_id).await)
+		}
+	}
+
+	pub async fn read_file(
+		user_id: Id<UserMarker>,
+	) -> BotResult<Self> {
+		//	Retrieve file
+		let path = format!("data/users/user_{}.json", user_id.get());
+		if Path::new(&path).exists() {
+			let contents = fs::read_to_string(&path)
+				.map_err(|e| format!("Error reading user data: {}", e))?;
+			let data: UserData = serde_json::from_str(&contents)
+				.map_err(|e| format!("Error parsing user data JSON: {}", e))?;
+	
+			Ok(data)
+		} else { Err(format!("Data at {:?} not found", path).into()) }
+	}
+
+	pub async fn write_file(
+		&self,
+	) -> BotResult<()> {
+		//	Construct path
+		let path = format!("data/users/user_{}.json", self.id.get());
+		fs::create_dir_all("data/users")
+			.map_err(|e| format!("Error creating data directory: {}", e))?;
+
+		//	Write
+		let serialized = serde_json::to_string(self)
+			.map_err(|e| format!("Error serializing data: {}", e))?;
+	
+		fs::write(&path, serialized)
+			.map_err(|e| format!("Error writing to file: {}", e))?;
+	
+		Ok(())
+	}
+}
+
+//		Channel Data
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelData {
+	pub id: Id<ChannelMarker>,
+
+	//	Recent rolls, oldest first
+	#[serde(default)]
+	pub history: VecDeque<RollRecord>,
+
+	#[serde(default)]
+	pub initiative: Initiative,
+
+	//	Overrides the guild's game system
+	#[serde(default)]
+	pub system: Option<GameSystem>,
+
+	//	Rolls made here so far, which numbers them
+	#[serde(default)]
+	pub rolls: u64,
+	//	The seed the next fair roll will use, already committed to; kept secret
+	#[serde(default)]
+	pub next_seed: Option<u64>,
+
+	//	The channel's deck of cards, once someone starts one
+	#[serde(default)]
+	pub deck: Option<Deck>,
+}
+
+impl ChannelData {
+	pub async fn lock(
+		channel_id: Id<ChannelMarker>,
+	) -> OwnedMutexGuard<()> {
+		lock(&CHANNEL_LOCKS, channel_id).await
+	}
+
+	pub async fn new(id: Id<ChannelMarker>) -> Self {
+		let out = Self {
+			id,
+
+			history: VecDeque::new(),
+
+			initiative: Initiative::default(),
+
+			system: None,
+
+			rolls: 0,
+			next_seed: None,
+
+			deck: None,
+		};
+		let _ = out.write_file().await;
+
+		out
+	}
+
+	//	Made fresh if the channel has none yet, but never over a file that won't parse.
+	pub async fn read_or_new(
+		channel_id: Id<ChannelMarker>,
+	) -> BotResult<Self> {
+		let path = format!("data/channels/channel_{}.json", channel_id.get());
+		if Path::new(&path).exists() {
+			Self::read_file(channel_id).await
+				.inspect_err(|e| println!("[DATA] {}", e))
+		} else {
+			Ok(Self::new(channel_id).await)
+		}
+	}
+
+	pub async fn read_file(
+		channel_id: Id<ChannelMarker>,
+	) -> BotResult<Self> {
+		//	Retrieve file
+		let path = format!("data/channels/channel_{}.json", channel_id.get());
+		if Path::new(&path).exists() {
+			let contents = fs::read_to_string(&path)
+				.map_err(|e| format!("Error reading channel data: {}", e))?;
+			let data: ChannelData = serde_json::from_str(&contents)
+				.map_err(|e| format!("Error parsing channel data JSON: {}", e))?;
+	
+			Ok(data)
+		} else { Err(format!("Data at {:?} not found", path).into()) }
+	}
+
+	pub async fn write_file(
+		&self,
+	) -> BotResult<()> {
+		//	Construct path
+		let path = format!("data/channels/channel_{}.json", self.id.get());
+		fs::create_dir_all("data/channels")
+			.map_err(|e| format!("Error creating data directory: {}", e))?;
+
+		//	Write
+		let serialized = serde_json::to_string(self)
+			.map_err(|e| format!("Error serializing data: {}", e))?;
+	
+		fs::write(&path, serialized)
+			.map_err(|e| format!("Error writing to file: {}", e))?;
+	
+		Ok(())
+	}
+}
+
 //		Bot Data
+#[allow(dead_code)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BotData {
 	pub issue_map: HashMap<i64, String>
 }
 
+#[allow(dead_code)]
 impl BotData {
 	pub async fn new() -> Self {
-		let out = Self {
+		Self {
 			issue_map: HashMap::new(),
-		};
-		
-		out
+		}
 	}
 
 	pub async fn read_or_new() -> BotResult<Self> {
@@ -1,24 +1,65 @@
 //		Imports
-use std::{};
-
 use twilight_model::{
-	application::interaction::{
-		Interaction,
-		InteractionType,
-		InteractionData,
-	}, 
-	http::interaction::InteractionResponse
+	application::{
+		command::{CommandOption, CommandOptionType},
+		interaction::{
+			Interaction,
+			InteractionType,
+			InteractionData,
+			application_command::{CommandDataOption, CommandOptionValue},
+		},
+	},
+	channel::message::{AllowedMentions, MessageFlags},
+	http::interaction::{
+		InteractionResponse,
+		InteractionResponseData,
+		InteractionResponseType
+	}
 };
-use twilight_http::Client;
 
 use crate::{
-	BotResult, InteractionContext
+	BotResult, InteractionContext,
+	commands
 };
 
 //		Data
+//	Options of the /roll command
+fn roll_options() -> Vec<CommandOption> {
+	let option = |name: &str, description: &str, kind: CommandOptionType, required: bool| CommandOption{
+		autocomplete: None,
+		channel_types: None,
+		choices: None,
+		description: description.to_owned(),
+		description_localizations: None,
+		kind,
+		max_length: None,
+		max_value: None,
+		min_length: None,
+		min_value: None,
+		name: name.to_owned(),
+		name_localizations: None,
+		options: None,
+		required: Some(required),
+	};
 
+	vec![
+		option("expr", "What to roll, e.g. 4d6kh3 or 1d20+5", CommandOptionType::String, true),
+		option("hidden", "Only show the result to you and the GM", CommandOptionType::Boolean, false),
+	]
+}
 
 //		Functions
+//	Tells Discord about the slash commands that are actually handled.
+pub async fn register_commands(ctx: &InteractionContext) -> BotResult<()> {
+	let options = roll_options();
+	ctx.interaction().create_global_command()
+		.chat_input("roll", "Roll some dice")?
+		.command_options(&options)?
+		.await?;
+
+	Ok(())
+}
+
 pub async fn handle_interaction(
 	interaction: Interaction, 
 	ctx: InteractionContext
@@ -44,6 +85,20 @@ pub async fn handle_interaction(
 
 			//	Get handler
 			match name {
+				"roll" => {
+					let (expr, hidden) = read_roll_options(&data.options);
+					let (content, ephemeral) = commands::dice::roll_command(&ctx, &inter, &expr, hidden).await?;
+
+					InteractionResponse{
+						kind: InteractionResponseType::ChannelMessageWithSource,
+						data: Some(InteractionResponseData{
+							allowed_mentions: Some(AllowedMentions::default()),
+							content: Some(content),
+							flags: ephemeral.then_some(MessageFlags::EPHEMERAL),
+							..Default::default()
+						})
+					}
+				}
 				"dice" => {
 					todo!()
 				}
@@ -53,7 +108,7 @@ pub async fn handle_interaction(
 				_ => return Err("Bad command".into())
 			}
 		},
-		InteractionData::MessageComponent(data) => {
+		InteractionData::MessageComponent(_data) => {
 			todo!()
 		},
 		_ => { 
@@ -77,9 +132,23 @@ pub async fn handle_interaction(
 	Ok(())
 }
 
+fn read_roll_options(options: &[CommandDataOption]) -> (String, bool) {
+	let mut expr = String::new();
+	let mut hidden = false;
+	for option in options {
+		match (option.name.as_str(), &option.value) {
+			("expr", CommandOptionValue::String(value)) => expr = value.clone(),
+			("hidden", CommandOptionValue::Boolean(value)) => hidden = *value,
+			_ => {}
+		}
+	}
+
+	(expr, hidden)
+}
+
 pub async fn handle_autocomplete(
-	ac: Interaction,
-	ctx: InteractionContext
+	_ac: Interaction,
+	_ctx: InteractionContext
 ) -> BotResult<()> {
 
 	Ok(())
@@ -2,7 +2,7 @@
 use std::{
 	env, 
 	sync::Arc,
-	error::Error, str::FromStr
+	error::Error,
 };
 use dotenv::dotenv;
 
@@ -12,7 +12,7 @@ use twilight_model::{
 		Id,
 		marker::ApplicationMarker
 	},
-	gateway::{Intents, payload::incoming::MessageCreate}, channel::Message
+	gateway::Intents,
 };
 use twilight_http::{
 	Client,
@@ -21,10 +21,11 @@ use twilight_http::{
 
 //  User stuff
 mod data;
+mod permissions;
 
 mod interaction;
 use crate::{
-	interaction::handle_interaction,
+	interaction::{handle_interaction, register_commands},
 };
 
 mod commands;
@@ -64,11 +65,12 @@ async fn main() -> BotResult<()> {
 		.expect("Expected a token in the environment");
 	println!("[MAIN] Token: {:?}", token);
 
-	let intents = Intents::GUILD_MESSAGES | Intents::MESSAGE_CONTENT;
+	let intents = Intents::GUILD_MESSAGES | Intents::DIRECT_MESSAGES | Intents::MESSAGE_CONTENT;
 	let http = Arc::new(Client::new(token.clone()));
 	let app_id = http.current_user_application().await?.model().await?.id;
 
 	let ctx = InteractionContext::new(http, app_id);
+	register_commands(&ctx).await?;
 
 	//	Create shard
 	let mut shard = Shard::new(ShardId::ONE, token, intents);
@@ -100,12 +102,9 @@ pub async fn handle_event(
 		_ => { return Ok(()) }
 	};
 
-	match event {
-		Event::MessageCreate(msg) => {
-			ctx.http.create_message(msg.channel_id)
-				.content(&err_msg)?.await?;
-		}
-		_ => {}
+	if let Event::MessageCreate(msg) = event {
+		ctx.http.create_message(msg.channel_id)
+			.content(&err_msg)?.await?;
 	}
 
 	Ok(())
@@ -138,36 +137,35 @@ pub async fn handle_event_internal(
 			}
 
 			if lc.contains("pilebot why are you like this") {
-				let reply: String = format!("i just am");
+				let reply: String = "i just am".to_string();
 				ctx.http.create_message(msg.channel_id).content(&reply)?.await?;
 			}
 			
 			//	Actual commands
-			if let None = msg.content.split_once(' ') { return Ok(()) }
-			let (mut name, mut rest) = msg.content.split_once(' ').unwrap();
-			
-			name = &name[1..];
-			rest = rest.trim();
+			let (name, rest) = command(&msg.content);
 
 			match name {
 				"dice" => commands::dice::dice(ctx, msg.clone(), rest).await?,
 				"flavor" => commands::flavor::flavor(ctx, msg.clone(), rest).await?,
+				"var" => commands::var::var(ctx, msg.clone(), rest).await?,
+				"init" => commands::init::init(ctx, msg.clone(), rest).await?,
+				"table" => commands::table::table(ctx, msg.clone(), rest).await?,
+				"deck" => commands::deck::deck(ctx, msg.clone(), rest).await?,
+				"choose" | "shuffle" | "flip" => commands::random::random(ctx, msg.clone(), name, rest).await?,
 				"role" => {
 					ctx.http.create_message(msg.channel_id)
 						.content("role command unimplemented")?.await?;
 				}
-				
-				_ => {}
+
+				//	Inline [[rolls]] can turn up anywhere in an ordinary message
+				_ => commands::dice::inline(ctx, msg.clone()).await?
 			}
 		}
 
 		// "Interactions" are the proper term for Discord's slash commands. The ideal would be
 		// to move to an interaction-based architecture rather than what currently exists.
 		Event::InteractionCreate(interaction) => {
-			match handle_interaction(interaction.0, ctx).await {
-				Ok(_) => {}
-				Err(e) => {}
-			}
+			let _ = handle_interaction(interaction.0, ctx).await;
 		}
  
 		//	Handle other things.
@@ -176,3 +174,27 @@ pub async fn handle_event_internal(
 
 	Ok(())
 }
+
+//	"!name rest" as the command's name and its arguments, which may be empty. Anything
+//	else has no name, and is only checked for inline rolls.
+fn command(content: &str) -> (&str, &str) {
+	let Some(content) = content.strip_prefix('!') else { return ("", "") };
+	let (name, rest) = content.split_once(' ').unwrap_or((content, ""));
+
+	(name, rest.trim())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn commands() {
+		assert_eq!(command("!flip"), ("flip", ""));
+		assert_eq!(command("!flip 3"), ("flip", "3"));
+		assert_eq!(command("!dice  2d6 + 1 "), ("dice", "2d6 + 1"));
+		assert_eq!(command("flip"), ("", ""));
+		assert_eq!(command("i rolled [[1d20]]"), ("", ""));
+		assert_eq!(command(""), ("", ""));
+	}
+}
@@ -0,0 +1,36 @@
+//		Imports
+use twilight_model::{
+	guild::Permissions,
+	id::{
+		Id, marker::{
+			GuildMarker,
+			RoleMarker,
+			UserMarker
+		}
+	}
+};
+
+use crate::{
+	BotResult,
+	InteractionContext
+};
+
+//		Functions
+//	Whether a member can change the bot's settings for a guild - the owner, or anyone
+//	with Manage Server or Administrator through their roles.
+pub async fn can_manage_guild(
+	ctx: &InteractionContext,
+	guild_id: Id<GuildMarker>,
+	user_id: Id<UserMarker>,
+	roles: &[Id<RoleMarker>]
+) -> BotResult<bool> {
+	let guild = ctx.http.guild(guild_id).await?.model().await?;
+	if guild.owner_id == user_id { return Ok(true) }
+
+	//	@everyone shares its id with the guild
+	let permissions = guild.roles.iter()
+		.filter(|role| role.id.get() == guild_id.get() || roles.contains(&role.id))
+		.fold(Permissions::empty(), |acc, role| acc | role.permissions);
+
+	Ok(permissions.intersects(Permissions::ADMINISTRATOR | Permissions::MANAGE_GUILD))
+}